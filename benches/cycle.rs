@@ -0,0 +1,50 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use opcode::{CPU, Quirks};
+
+// Small loop that touches the common instruction groups: register loads and arithmetic,
+// a skip, a call and return, a font lookup and a draw. Runs forever without erroring
+const ROM: [u8; 26] = [
+    0x60, 0x00, // 200: LD V0, 0x00
+    0x61, 0x00, // 202: LD V1, 0x00
+    0xA0, 0x50, // 204: LD I, 0x050
+    0xD0, 0x15, // 206: DRW V0, V1, 5
+    0x70, 0x01, // 208: ADD V0, 0x01
+    0x71, 0x02, // 20A: ADD V1, 0x02
+    0x80, 0x14, // 20C: ADD V0, V1
+    0x81, 0x06, // 20E: SHR V1, V0
+    0x30, 0x00, // 210: SE V0, 0x00
+    0x22, 0x16, // 212: CALL 0x216
+    0x12, 0x04, // 214: JP 0x204
+    0xF0, 0x29, // 216: LD F, V0
+    0x00, 0xEE, // 218: RET
+];
+
+const CYCLES: u64 = 1000;
+
+fn parse_opcode(c: &mut Criterion) {
+    // Decoding alone, to compare against the full cycle below
+    let opcodes: Vec<u16> = ROM.chunks(2).map(|w| (w[0] as u16) << 8 | w[1] as u16).collect();
+    let mut group = c.benchmark_group("parse_opcode");
+    group.throughput(Throughput::Elements(opcodes.len() as u64));
+    group.bench_function("rom", |b| b.iter(|| {
+        for &opcode in opcodes.iter() {
+            black_box(CPU::parse_opcode(black_box(opcode)).unwrap());
+        };
+    }));
+    group.finish();
+}
+
+fn cycle(c: &mut Criterion) {
+    // Throughput is reported per cycle, so the result reads as cycles per second
+    let mut cpu = CPU::new(Quirks::default());
+    cpu.load_rom_bytes(&ROM).unwrap();
+    let mut group = c.benchmark_group("cycle");
+    group.throughput(Throughput::Elements(CYCLES));
+    group.bench_function("rom", |b| b.iter(|| {
+        cpu.run_cycles(CYCLES as usize).unwrap();
+    }));
+    group.finish();
+}
+
+criterion_group!(benches, parse_opcode, cycle);
+criterion_main!(benches);
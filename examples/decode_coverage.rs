@@ -0,0 +1,48 @@
+// Runs every possible opcode through parse_opcode and counts how many land in each instruction category.
+// Every opcode has to come back as either an Instruction or a DecodeError naming that opcode,
+// anything else (including a panic) fails the run.
+//
+//     cargo run --example decode_coverage
+
+use std::collections::BTreeMap;
+use std::panic;
+use std::process;
+use opcode::{DecodeErrorKind, CPU};
+
+fn main() {
+    let mut categories: BTreeMap<&'static str, u32> = BTreeMap::new();
+    let mut unknown = 0;
+    let mut unsupported = 0;
+    let mut failed = Vec::new();
+
+    for opcode in 0..=0xFFFFu16 {
+        match panic::catch_unwind(|| CPU::parse_opcode(opcode)) {
+            Ok(Ok(instruction)) => *categories.entry(instruction.category()).or_insert(0) += 1,
+            Ok(Err(e)) => {
+                if e.opcode != opcode {
+                    eprintln!("{:04X}: error reports opcode {:04X}", opcode, e.opcode);
+                    failed.push(opcode);
+                };
+                match e.kind {
+                    DecodeErrorKind::UnknownOpcode | DecodeErrorKind::UnknownSubOpcode { .. } => unknown += 1,
+                    DecodeErrorKind::UnsupportedInstruction => unsupported += 1,
+                };
+            },
+            Err(_) => failed.push(opcode),
+        };
+    }
+
+    for (category, count) in categories.iter() {
+        println!("{:>8} {}", count, category);
+    };
+    println!("{:>8} unknown opcodes", unknown);
+    println!("{:>8} unsupported instructions", unsupported);
+
+    let decoded: u32 = categories.values().sum();
+    println!("{} of 65536 opcodes decode to an instruction", decoded);
+
+    if !failed.is_empty() {
+        eprintln!("{} opcodes failed, starting with {:04X}", failed.len(), failed[0]);
+        process::exit(1);
+    };
+}
@@ -0,0 +1,89 @@
+// Headless regression check for test ROMs. Each <name>.ch8 in the directory is run for a fixed
+// number of cycles and its screen compared against <name>.txt, 64x32 lines of # (lit) and . (dark).
+// Drop a new ROM in alongside a reference to add it to the run.
+//
+//     cargo run --example rom_check -- <dir> [--cycles <n>] [--bless]
+//
+// --bless writes the current screen out as the reference instead of comparing,
+// for capturing a reference from a run that's been checked by eye.
+
+use std::env;
+use std::ffi::OsStr;
+use std::fs;
+use std::path::Path;
+use std::process;
+use opcode::{CPU, Quirks};
+
+fn check(rom: &Path, cycles: usize, bless: bool) -> Result<(), String> {
+    let bytes = fs::read(rom).map_err(|e| e.to_string())?;
+    let mut chip8 = CPU::new(Quirks::default());
+    chip8.load_rom_bytes(&bytes).map_err(|e| e.to_string())?;
+    chip8.run_cycles(cycles).map_err(|e| e.to_string())?;
+
+    let screen = chip8.framebuffer().to_string();
+    let reference = rom.with_extension("txt");
+    if bless {
+        return fs::write(&reference, screen).map_err(|e| e.to_string());
+    };
+
+    let expected = fs::read_to_string(&reference).map_err(|e| format!("{}: {}", reference.display(), e))?;
+    if expected.trim_end() == screen.trim_end() {
+        Ok(())
+    } else {
+        Err(format!("screen doesn't match {}, got\n{}", reference.display(), screen))
+    }
+}
+
+fn main() {
+    let mut dir = None;
+    let mut cycles = 10_000;
+    let mut bless = false;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--cycles" => match args.next().map(|x| x.parse::<usize>()) {
+                Some(Ok(x)) => cycles = x,
+                _ => {
+                    eprintln!("--cycles needs a number");
+                    process::exit(2);
+                },
+            },
+            "--bless" => bless = true,
+            _ => dir = Some(arg),
+        };
+    };
+
+    let dir = match dir {
+        Some(x) => x,
+        None => {
+            eprintln!("Usage: rom_check <dir> [--cycles <n>] [--bless]");
+            process::exit(2);
+        },
+    };
+
+    let mut roms: Vec<_> = match fs::read_dir(&dir) {
+        Ok(entries) => entries.filter_map(|e| e.ok()).map(|e| e.path())
+            .filter(|p| p.extension() == Some(OsStr::new("ch8"))).collect(),
+        Err(e) => {
+            eprintln!("Error reading {}: {}", dir, e);
+            process::exit(2);
+        },
+    };
+    roms.sort();
+
+    let mut failures = 0;
+    for rom in roms.iter() {
+        match check(rom, cycles, bless) {
+            Ok(()) => println!("ok    {}", rom.display()),
+            Err(e) => {
+                println!("FAIL  {}: {}", rom.display(), e);
+                failures += 1;
+            },
+        };
+    };
+    println!("{} of {} ROMs passed", roms.len() - failures, roms.len());
+
+    if failures > 0 {
+        process::exit(1);
+    };
+}
@@ -0,0 +1,445 @@
+use std::collections::HashMap;
+use std::fmt;
+use crate::{Instruction, Target_Register, PROGRAM_START};
+
+// A small assembler for Octo's core syntax: `: name` labels, `#` comments, `v0 := 10`, `v0 += v1`,
+// `i := sprite`, `sprite v0 v1 5`, `jump`, `:call`, `return`, `if v0 == 3 then ...` and the rest of the
+// statements that map to a single opcode, several to a line if you like. A line of bare numbers emits
+// those bytes as-is for sprite data. Octo's macros, `<`/`>` comparisons and long jumps aren't supported.
+// Lines can also use the mnemonics Instruction's Display prints (LD V0, 0x0A, DRW V1, V2, 5, ...),
+// one per line, and `DW 0xNNNN` emits one big-endian word, which is how disassemble lists words that
+// don't decode. Labels can be used anywhere an address or value is expected. Output is meant to be
+// loaded at PROGRAM_START with load_rom_bytes
+
+#[derive(Debug, PartialEq)]
+pub struct AsmError {
+    pub line: usize, // 1-based line in the source
+    pub message: String,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for AsmError {}
+
+enum Operand {
+    V(u16), // V0-VF by number
+    I, DT, ST, K, F, HF, B, R,
+    IndirectI, // [I]
+    Value(String), // Number or label, resolved once every label's address is known
+}
+
+enum Item {
+    Bytes(Vec<String>),
+    Instruction(String, Vec<Operand>),
+}
+
+fn parse_operand(token: &str) -> Operand {
+    let upper = token.to_uppercase();
+    match upper.as_str() {
+        "I" => Operand::I,
+        "DT" => Operand::DT,
+        "ST" => Operand::ST,
+        "K" => Operand::K,
+        "F" => Operand::F,
+        "HF" => Operand::HF,
+        "B" => Operand::B,
+        "R" => Operand::R,
+        "[I]" => Operand::IndirectI,
+        x => match x.strip_prefix('V').filter(|n| n.len() == 1).map(|n| u16::from_str_radix(n, 16)) {
+            Some(Ok(n)) => Operand::V(n),
+            _ => Operand::Value(token.to_string()),
+        },
+    }
+}
+
+// Statements that can only be Octo, anything else is a mnemonic unless its second token is an assignment
+const OCTO_KEYWORDS: [&str; 18] = ["clear", "return", "exit", "hires", "lores", "scroll-down", "scroll-left",
+    "scroll-right", "jump", "jump0", ":call", "bcd", "save", "load", "saveflags", "loadflags", "sprite", "if"];
+const OCTO_ASSIGNMENTS: [&str; 9] = [":=", "+=", "-=", "=-", "|=", "&=", "^=", ">>=", "<<="];
+
+fn is_octo(tokens: &[&str]) -> bool {
+    matches!(tokens.first(), Some(t) if OCTO_KEYWORDS.contains(t)) || matches!(tokens.get(1), Some(t) if OCTO_ASSIGNMENTS.contains(t))
+}
+
+fn octo_statement(tokens: &[&str]) -> Result<(usize, &'static str, Vec<Operand>), String> {
+    // The Octo statement at the front of tokens as the mnemonic and operands it's shorthand for,
+    // plus how many tokens it took. `if ... then` is a skip over whatever statement comes next,
+    // so it skips when the condition is false
+    let arg = |n: usize| tokens.get(n).copied().ok_or_else(|| format!("{} is missing an operand", tokens[0]));
+    let op = |n: usize| arg(n).map(parse_operand);
+
+    let statement = match tokens[0] {
+        "clear" => (1, "CLS", vec![]),
+        "return" => (1, "RET", vec![]),
+        "exit" => (1, "EXIT", vec![]),
+        "hires" => (1, "HIGH", vec![]),
+        "lores" => (1, "LOW", vec![]),
+        "scroll-down" => (2, "SCD", vec![op(1)?]),
+        "scroll-left" => (1, "SCL", vec![]),
+        "scroll-right" => (1, "SCR", vec![]),
+        "jump" => (2, "JP", vec![op(1)?]),
+        "jump0" => (2, "JP", vec![Operand::V(0), op(1)?]),
+        ":call" => (2, "CALL", vec![op(1)?]),
+        "bcd" => (2, "LD", vec![Operand::B, op(1)?]),
+        "save" => (2, "LD", vec![Operand::IndirectI, op(1)?]),
+        "load" => (2, "LD", vec![op(1)?, Operand::IndirectI]),
+        "saveflags" => (2, "LD", vec![Operand::R, op(1)?]),
+        "loadflags" => (2, "LD", vec![op(1)?, Operand::R]),
+        "sprite" => (4, "DRW", vec![op(1)?, op(2)?, op(3)?]),
+        "if" => {
+            let (length, mnemonic, operands) = match arg(2)? {
+                "==" => (5, "SNE", vec![op(1)?, op(3)?]),
+                "!=" => (5, "SE", vec![op(1)?, op(3)?]),
+                "key" => (4, "SKNP", vec![op(1)?]),
+                "-key" => (4, "SKP", vec![op(1)?]),
+                x => return Err(format!("if {} isn't supported, only ==, !=, key and -key are", x)),
+            };
+            if arg(length - 1)? != "then" {
+                return Err(String::from("if needs a then after its condition"));
+            };
+            (length, mnemonic, operands)
+        },
+        target => match (target, arg(1)?, arg(2)?) {
+            ("delay", ":=", _) => (3, "LD", vec![Operand::DT, op(2)?]),
+            ("buzzer", ":=", _) => (3, "LD", vec![Operand::ST, op(2)?]),
+            ("i", ":=", "hex") => (4, "LD", vec![Operand::F, op(3)?]),
+            ("i", ":=", "bighex") => (4, "LD", vec![Operand::HF, op(3)?]),
+            (_, ":=", "random") => (4, "RND", vec![op(0)?, op(3)?]),
+            (_, ":=", "delay") => (3, "LD", vec![op(0)?, Operand::DT]),
+            (_, ":=", "key") => (3, "LD", vec![op(0)?, Operand::K]),
+            (_, ":=", _) => (3, "LD", vec![op(0)?, op(2)?]),
+            (_, "+=", _) => (3, "ADD", vec![op(0)?, op(2)?]),
+            (_, "-=", _) => (3, "SUB", vec![op(0)?, op(2)?]),
+            (_, "=-", _) => (3, "SUBN", vec![op(0)?, op(2)?]),
+            (_, "|=", _) => (3, "OR", vec![op(0)?, op(2)?]),
+            (_, "&=", _) => (3, "AND", vec![op(0)?, op(2)?]),
+            (_, "^=", _) => (3, "XOR", vec![op(0)?, op(2)?]),
+            (_, ">>=", _) => (3, "SHR", vec![op(0)?, op(2)?]),
+            (_, "<<=", _) => (3, "SHL", vec![op(0)?, op(2)?]),
+            _ => return Err(format!("{} isn't an Octo statement", target)),
+        },
+    };
+    Ok(statement)
+}
+
+fn parse_number(token: &str) -> Option<u16> {
+    let lower = token.to_lowercase();
+    if let Some(hex) = lower.strip_prefix("0x") {
+        u16::from_str_radix(hex, 16).ok()
+    } else if let Some(binary) = lower.strip_prefix("0b") {
+        u16::from_str_radix(binary, 2).ok()
+    } else {
+        lower.parse::<u16>().ok()
+    }
+}
+
+fn resolve(token: &str, max: u16, labels: &HashMap<String, u16>) -> Result<u16, String> {
+    let value = match parse_number(token) {
+        Some(x) => x,
+        None => match labels.get(token) {
+            Some(&address) => address,
+            None => return Err(format!("{} isn't a number or a known label", token)),
+        },
+    };
+    if value > max {
+        return Err(format!("{} doesn't fit, the most this operand can hold is 0x{:X}", token, max));
+    };
+    Ok(value)
+}
+
+fn instruction(mnemonic: &str, operands: &[Operand], labels: &HashMap<String, u16>) -> Result<Instruction, String> {
+    let addr = |v: &str| resolve(v, 0xFFF, labels);
+    let byte = |v: &str| resolve(v, 0xFF, labels).map(|x| x as u8);
+    let nibble = |v: &str| resolve(v, 0xF, labels).map(|x| x as u8);
+    let r = |x: &u16| Target_Register::u8_to_register(*x as u8);
+
+    let instruction = match (mnemonic, operands) {
+        ("NOP", []) => Instruction::NOP,
+        ("CLS", []) => Instruction::Display,
+        ("RET", []) => Instruction::Return,
+        ("SCD", [Operand::Value(n)]) => Instruction::SCRD { lines: nibble(n)? },
+        ("SCR", []) => Instruction::SCRR,
+        ("SCL", []) => Instruction::SCRL,
+        ("EXIT", []) => Instruction::Exit,
+        ("LOW", []) => Instruction::LOW,
+        ("HIGH", []) => Instruction::HIGH,
+        ("JP", [Operand::Value(a)]) => Instruction::JUMP { address: addr(a)? },
+        ("JP", [Operand::V(0), Operand::Value(a)]) => Instruction::JMP0 { address: addr(a)? },
+        ("CALL", [Operand::Value(a)]) => Instruction::Call { address: addr(a)? },
+        ("SE", [Operand::V(x), Operand::Value(n)]) => Instruction::SKEQ { register: r(x), value: byte(n)? },
+        ("SE", [Operand::V(x), Operand::V(y)]) => Instruction::SKREQ { register1: r(x), register2: r(y) },
+        ("SNE", [Operand::V(x), Operand::Value(n)]) => Instruction::SKNEQ { register: r(x), value: byte(n)? },
+        ("SNE", [Operand::V(x), Operand::V(y)]) => Instruction::SKRNEQ { register1: r(x), register2: r(y) },
+        ("LD", [Operand::V(x), Operand::Value(n)]) => Instruction::SET { register: r(x), value: byte(n)? },
+        ("LD", [Operand::V(x), Operand::V(y)]) => Instruction::COPYR { register1: r(x), register2: r(y) },
+        ("LD", [Operand::I, Operand::Value(a)]) => Instruction::SETI { value: addr(a)? },
+        ("LD", [Operand::V(x), Operand::DT]) => Instruction::SETXD { register: r(x) },
+        ("LD", [Operand::V(x), Operand::K]) => Instruction::STORE { register: r(x) },
+        ("LD", [Operand::DT, Operand::V(x)]) => Instruction::SETD { register: r(x) },
+        ("LD", [Operand::ST, Operand::V(x)]) => Instruction::SETS { register: r(x) },
+        ("LD", [Operand::F, Operand::V(x)]) => Instruction::SPRITE { register: r(x) },
+        ("LD", [Operand::B, Operand::V(x)]) => Instruction::BCD { register: r(x) },
+        ("LD", [Operand::IndirectI, Operand::V(x)]) => Instruction::DUMP { register: r(x) },
+        ("LD", [Operand::V(x), Operand::IndirectI]) => Instruction::LOAD { register: r(x) },
+        ("LD", [Operand::R, Operand::V(x)]) => Instruction::DUMPF { register: r(x) },
+        ("LD", [Operand::V(x), Operand::R]) => Instruction::LOADF { register: r(x) },
+        ("LD", [Operand::HF, Operand::V(x)]) => Instruction::BigSprite { register: r(x) },
+        ("ADD", [Operand::V(x), Operand::Value(n)]) => Instruction::ADD { register: r(x), value: byte(n)? },
+        ("ADD", [Operand::V(x), Operand::V(y)]) => Instruction::ADDR { register1: r(x), register2: r(y) },
+        ("ADD", [Operand::I, Operand::V(x)]) => Instruction::ADDI { register: r(x) },
+        ("OR", [Operand::V(x), Operand::V(y)]) => Instruction::OR { register1: r(x), register2: r(y) },
+        ("AND", [Operand::V(x), Operand::V(y)]) => Instruction::AND { register1: r(x), register2: r(y) },
+        ("XOR", [Operand::V(x), Operand::V(y)]) => Instruction::XOR { register1: r(x), register2: r(y) },
+        ("SUB", [Operand::V(x), Operand::V(y)]) => Instruction::SUBX { register1: r(x), register2: r(y) },
+        ("SHR", [Operand::V(x)]) => Instruction::SHFTR { register1: r(x), register2: r(x) },
+        ("SHR", [Operand::V(x), Operand::V(y)]) => Instruction::SHFTR { register1: r(x), register2: r(y) },
+        ("SUBN", [Operand::V(x), Operand::V(y)]) => Instruction::SUBY { register1: r(x), register2: r(y) },
+        ("SHL", [Operand::V(x)]) => Instruction::SHFTL { register1: r(x), register2: r(x) },
+        ("SHL", [Operand::V(x), Operand::V(y)]) => Instruction::SHFTL { register1: r(x), register2: r(y) },
+        ("RND", [Operand::V(x), Operand::Value(n)]) => Instruction::RAND { register: r(x), value: byte(n)? },
+        ("DRW", [Operand::V(x), Operand::V(y), Operand::Value(n)]) => Instruction::DRAW { register1: r(x), register2: r(y), height: nibble(n)? },
+        ("SKP", [Operand::V(x)]) => Instruction::SKKEQ { register: r(x) },
+        ("SKNP", [Operand::V(x)]) => Instruction::SKKNEQ { register: r(x) },
+        _ => return Err(format!("{} doesn't take these operands", mnemonic)),
+    };
+    Ok(instruction)
+}
+
+fn encode(mnemonic: &str, operands: &[Operand], labels: &HashMap<String, u16>) -> Result<u16, String> {
+    // Operands were range checked on the way in, so to_opcode only fails on an instruction it has no opcode for
+    if let ("DW", [Operand::Value(word)]) = (mnemonic, operands) {
+        // A raw word, the way the disassembler lists data it can't decode
+        return resolve(word, 0xFFFF, labels);
+    };
+    let instruction = instruction(mnemonic, operands, labels)?;
+    instruction.to_opcode().ok_or_else(|| format!("{} has no single opcode", instruction))
+}
+
+pub fn assemble(source: &str) -> Result<Vec<u8>, AsmError> {
+    // First pass works out where everything goes so labels can be used before they're defined
+    let mut labels = HashMap::new();
+    let mut items = Vec::new();
+    let mut address = PROGRAM_START;
+
+    for (i, line) in source.lines().enumerate() {
+        let line_number = i + 1;
+        let error = |message: String| AsmError { line: line_number, message };
+        let code = line.split('#').next().unwrap_or("").trim();
+        if code.is_empty() {
+            continue;
+        };
+
+        if let Some(name) = code.strip_prefix(':') {
+            let name = name.trim();
+            if name.is_empty() || name.contains(char::is_whitespace) {
+                return Err(error(String::from("labels are written `: name`, one per line")));
+            };
+            if labels.insert(name.to_string(), address).is_some() {
+                return Err(error(format!("label {} is defined twice", name)));
+            };
+            continue;
+        };
+
+        let tokens: Vec<&str> = code.split_whitespace().collect();
+        if is_octo(&tokens) {
+            let mut rest = &tokens[..];
+            while !rest.is_empty() {
+                let (length, mnemonic, operands) = octo_statement(rest).map_err(error)?;
+                address = address.wrapping_add(2);
+                items.push((line_number, Item::Instruction(mnemonic.to_string(), operands)));
+                rest = &rest[length..];
+            };
+            continue;
+        };
+
+        let mut words = code.splitn(2, char::is_whitespace);
+        let first = words.next().unwrap_or("");
+        if parse_number(first).is_some() {
+            let bytes: Vec<String> = code.split_whitespace().map(String::from).collect();
+            address = address.wrapping_add(bytes.len() as u16);
+            items.push((line_number, Item::Bytes(bytes)));
+        } else {
+            let operands = words.next().unwrap_or("").split(',')
+                .map(str::trim).filter(|x| !x.is_empty()).map(parse_operand).collect();
+            address = address.wrapping_add(2);
+            items.push((line_number, Item::Instruction(first.to_uppercase(), operands)));
+        };
+    };
+
+    let mut rom = Vec::new();
+    for (line, item) in items.iter() {
+        let error = |message: String| AsmError { line: *line, message };
+        match item {
+            Item::Bytes(bytes) => {
+                for byte in bytes.iter() {
+                    rom.push(resolve(byte, 0xFF, &labels).map_err(error)? as u8);
+                };
+            },
+            Item::Instruction(mnemonic, operands) => {
+                let opcode = encode(mnemonic, operands, &labels).map_err(error)?;
+                rom.push((opcode >> 8) as u8);
+                rom.push(opcode as u8);
+            },
+        };
+    };
+    Ok(rom)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::disassemble;
+
+    // Every instruction form in the form Instruction's Display prints it
+    const LISTING: &str = "CLS
+RET
+SCD 3
+SCR
+SCL
+EXIT
+LOW
+HIGH
+JP 0x234
+JP V0, 0x234
+CALL 0x345
+SE VA, 0x12
+SE VA, VB
+SNE VA, 0x12
+SNE VA, VB
+LD VA, 0x12
+LD VA, VB
+LD I, 0x123
+LD VA, DT
+LD VA, K
+LD DT, VA
+LD ST, VA
+LD F, VA
+LD HF, VA
+LD B, VA
+LD [I], VA
+LD VA, [I]
+LD R, VA
+LD VA, R
+ADD VA, 0x12
+ADD VA, VB
+ADD I, VA
+OR VA, VB
+AND VA, VB
+XOR VA, VB
+SUB VA, VB
+SHR VA, VB
+SUBN VA, VB
+SHL VA, VB
+RND VA, 0x12
+DRW VA, VB, 5
+SKP VA
+SKNP VA";
+
+    fn listing(bytes: &[u8]) -> String {
+        disassemble(bytes).iter().map(|(_, decoded)| match decoded {
+            Ok(instruction) => instruction.to_string(),
+            Err(e) => format!("DW 0x{:04X}", e.opcode),
+        }).collect::<Vec<_>>().join("\n")
+    }
+
+    #[test]
+    fn disassembly_reads_back_as_the_source() {
+        let rom = assemble(LISTING).unwrap();
+        assert_eq!(rom.len(), LISTING.lines().count() * 2);
+        assert_eq!(listing(&rom), LISTING);
+    }
+
+    #[test]
+    fn disassembly_assembles_back_to_the_same_bytes() {
+        let source = "
+            # count V0 up to 5 then stop
+            : start
+            ld v0, 0
+            : loop
+            add v0, 1
+            se v0, 5
+            jp loop
+            : done
+            jp done
+        ";
+        let rom = assemble(source).unwrap();
+        assert_eq!(rom, vec![0x60, 0x00, 0x70, 0x01, 0x30, 0x05, 0x12, 0x02, 0x12, 0x08]);
+        assert_eq!(assemble(&listing(&rom)).unwrap(), rom);
+    }
+
+    #[test]
+    fn octo_assembles_to_the_same_bytes_as_mnemonics() {
+        let octo = "
+            : main
+            clear
+            v0 := 0  v1 := 0x12
+            i := digits
+            : loop
+            sprite v0 v1 5
+            v0 += 5
+            v1 += v0
+            v2 := random 0xFF
+            i := hex v2
+            if v0 != 60 then jump loop
+            if v3 -key then :call done
+            delay := v0
+            save v3
+            jump0 main
+            : done
+            return
+            : digits
+            0xF0 0x90 0x90 0x90 0xF0
+        ";
+        let mnemonics = "
+            : main
+            CLS
+            LD V0, 0
+            LD V1, 0x12
+            LD I, digits
+            : loop
+            DRW V0, V1, 5
+            ADD V0, 5
+            ADD V1, V0
+            RND V2, 0xFF
+            LD F, V2
+            SE V0, 60
+            JP loop
+            SKP V3
+            CALL done
+            LD DT, V0
+            LD [I], V3
+            JP V0, main
+            : done
+            RET
+            : digits
+            0xF0 0x90 0x90 0x90 0xF0
+        ";
+        assert_eq!(assemble(octo).unwrap(), assemble(mnemonics).unwrap());
+    }
+
+    #[test]
+    fn octo_if_skips_when_the_condition_is_false() {
+        let rom = assemble("if v0 == 3 then v1 := 1\nif v0 == v2 then return\nif v4 key then clear").unwrap();
+        assert_eq!(rom, vec![0x40, 0x03, 0x61, 0x01, 0x90, 0x20, 0x00, 0xEE, 0xE4, 0xA1, 0x00, 0xE0]);
+    }
+
+    #[test]
+    fn octo_errors_name_the_line() {
+        let e = assemble("v0 := 1\nif v0 < 3 then clear").unwrap_err();
+        assert_eq!(e.line, 2);
+        assert!(assemble("if v0 == 3 jump 0x200").is_err());
+        assert!(assemble("sprite v0 v1").is_err());
+    }
+
+    #[test]
+    fn undecodable_words_round_trip_as_dw() {
+        // A sprite row pair that isn't an opcode, between two instructions
+        let rom = vec![0x60, 0x01, 0xFF, 0xFF, 0x12, 0x00];
+        assert_eq!(listing(&rom), "LD V0, 0x01\nDW 0xFFFF\nJP 0x200");
+        assert_eq!(assemble(&listing(&rom)).unwrap(), rom);
+    }
+}
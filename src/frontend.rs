@@ -0,0 +1,131 @@
+use std::thread;
+use std::time::Duration;
+use sdl2::audio::{AudioCallback, AudioDevice, AudioSpecDesired};
+use sdl2::event::Event;
+use sdl2::keyboard::Keycode;
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::render::Canvas;
+use sdl2::video::Window;
+use sdl2::EventPump;
+use crate::{run_with, Framebuffer, Frontend, KeyMap, CPU, TIMER_HZ};
+
+const SCALE: u32 = 10; // Each low resolution pixel is drawn as a SCALE x SCALE square
+const TONE_HZ: f32 = 440.0;
+
+struct SquareWave {
+    phase_inc: f32,
+    phase: f32,
+    volume: f32,
+}
+
+impl AudioCallback for SquareWave {
+    type Channel = f32;
+
+    fn callback(&mut self, out: &mut [f32]) {
+        for x in out.iter_mut() {
+            *x = if self.phase <= 0.5 { self.volume } else { -self.volume };
+            self.phase = (self.phase + self.phase_inc) % 1.0;
+        };
+    }
+}
+
+fn keypad(keymap: &KeyMap, key: Keycode) -> Option<u8> {
+    // SDL names printable keys by their character, e.g. "Q" or "1", anything longer isn't on the keypad
+    let name = key.name();
+    let mut chars = name.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => keymap.map_key(c),
+        _ => None,
+    }
+}
+
+struct SdlFrontend {
+    canvas: Canvas<Window>,
+    events: EventPump,
+    beeper: AudioDevice<SquareWave>,
+    keymap: KeyMap,
+    quit: bool, // Set once the window is closed or Escape is pressed
+}
+
+impl Frontend for SdlFrontend {
+    fn poll_input(&mut self) -> Vec<(u8, bool)> {
+        let mut keys = Vec::new();
+        for event in self.events.poll_iter() {
+            match event {
+                Event::Quit { .. } | Event::KeyDown { keycode: Some(Keycode::Escape), .. } => self.quit = true,
+                Event::KeyDown { keycode: Some(key), .. } => {
+                    if let Some(k) = keypad(&self.keymap, key) {
+                        keys.push((k, true));
+                    };
+                },
+                Event::KeyUp { keycode: Some(key), .. } => {
+                    if let Some(k) = keypad(&self.keymap, key) {
+                        keys.push((k, false));
+                    };
+                },
+                _ => (),
+            };
+        };
+        keys
+    }
+
+    fn present(&mut self, fb: &Framebuffer) {
+        self.canvas.set_draw_color(Color::RGB(0, 0, 0));
+        self.canvas.clear();
+        self.canvas.set_draw_color(Color::RGB(255, 255, 255));
+        // The window stays the same size, high resolution just draws smaller pixels
+        let (width, _) = fb.resolution();
+        let size = 64 * SCALE / width as u32;
+        for (y, row) in fb.rows().enumerate() {
+            for (x, &pixel) in row.iter().enumerate() {
+                if pixel {
+                    // A failed rectangle only loses one pixel for one frame, not worth stopping over
+                    let _ = self.canvas.fill_rect(Rect::new(x as i32 * size as i32, y as i32 * size as i32, size, size));
+                };
+            };
+        };
+        self.canvas.present();
+    }
+
+    fn beep(&mut self, on: bool) {
+        if on {
+            self.beeper.resume();
+        } else {
+            self.beeper.pause();
+        };
+    }
+
+    fn quit_requested(&self) -> bool {
+        self.quit
+    }
+}
+
+pub fn run(cpu: &mut CPU, cycles_per_second: u32) -> Result<(), String> {
+    // Runs the ROM in a window through run_with. Closing the window or pressing Escape stops the emulator,
+    // and a halted program's last frame stays up until then
+    let sdl = sdl2::init()?;
+    let window = sdl.video()?
+        .window("opcode", 64 * SCALE, 32 * SCALE)
+        .position_centered()
+        .build()
+        .map_err(|e| e.to_string())?;
+    let canvas = window.into_canvas().build().map_err(|e| e.to_string())?;
+    let events = sdl.event_pump()?;
+
+    let spec = AudioSpecDesired { freq: Some(44100), channels: Some(1), samples: None };
+    let beeper = sdl.audio()?.open_playback(None, &spec, |spec| SquareWave {
+        phase_inc: TONE_HZ / spec.freq as f32,
+        phase: 0.0,
+        volume: 0.25,
+    })?;
+
+    let mut frontend = SdlFrontend { canvas, events, beeper, keymap: KeyMap::default(), quit: false };
+    run_with(cpu, &mut frontend, cycles_per_second).map_err(|e| e.to_string())?;
+
+    while !frontend.quit_requested() {
+        frontend.poll_input();
+        thread::sleep(Duration::from_secs(1) / TIMER_HZ);
+    };
+    Ok(())
+}
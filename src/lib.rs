@@ -0,0 +1,4108 @@
+use std::cell::Cell;
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::io::{BufWriter, Read, Write};
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
+use rand::prelude::*;
+#[cfg(feature = "serialize")]
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "asm")]
+pub mod asm;
+#[cfg(feature = "sdl")]
+pub mod frontend;
+
+#[allow(non_snake_case)]
+#[derive(Clone, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+pub struct Registers {
+    pub V: [u8; 16], // V0 through VF, indexed by register number
+    pub I: u16, pub PC: u16,
+}
+
+impl Registers {
+    fn new() -> Registers {
+        Registers {
+            V: [0u8; 16],
+            I: 0, PC: 0,
+        }
+    }
+}
+
+impl fmt::Debug for Registers {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // Print V0-VF by name rather than as a bare array
+        const NAMES: [&str; 16] = [
+            "V0", "V1", "V2", "V3", "V4", "V5", "V6", "V7",
+            "V8", "V9", "VA", "VB", "VC", "VD", "VE", "VF",
+        ];
+
+        let mut s = f.debug_struct("Registers");
+        for (name, value) in NAMES.iter().zip(self.V.iter()) {
+            s.field(name, value);
+        };
+        s.field("I", &self.I).field("PC", &self.PC).finish()
+    }
+}
+
+#[allow(dead_code)]
+#[allow(non_camel_case_types)]
+#[derive(Debug, Clone, PartialEq)]
+pub enum Target_Register {
+    V0, V1, V2, V3, V4, V5, V6, V7,
+    V8, V9, VA, VB, VC, VD, VE, VF,
+    I, PC,
+}
+
+impl Target_Register {
+    pub fn u8_to_register(value: u8) -> Target_Register {
+        // Only the 16 general purpose registers have numbers, callers mask the nibble out of the opcode first
+        match value {
+            0x0 => Target_Register::V0,
+            0x1 => Target_Register::V1,
+            0x2 => Target_Register::V2,
+            0x3 => Target_Register::V3,
+            0x4 => Target_Register::V4,
+            0x5 => Target_Register::V5,
+            0x6 => Target_Register::V6,
+            0x7 => Target_Register::V7,
+            0x8 => Target_Register::V8,
+            0x9 => Target_Register::V9,
+            0xA => Target_Register::VA,
+            0xB => Target_Register::VB,
+            0xC => Target_Register::VC,
+            0xD => Target_Register::VD,
+            0xE => Target_Register::VE,
+            0xF => Target_Register::VF,
+            _ => panic!("u8_to_register: 0x{:X} isn't a register number, expected 0x0-0xF", value),
+        }
+    }
+
+    pub fn register_to_u8(register: &Target_Register) -> u8 {
+        match register {
+            Target_Register::V0 => 0x0,
+            Target_Register::V1 => 0x1,
+            Target_Register::V2 => 0x2,
+            Target_Register::V3 => 0x3,
+            Target_Register::V4 => 0x4,
+            Target_Register::V5 => 0x5,
+            Target_Register::V6 => 0x6,
+            Target_Register::V7 => 0x7,
+            Target_Register::V8 => 0x8,
+            Target_Register::V9 => 0x9,
+            Target_Register::VA => 0xA,
+            Target_Register::VB => 0xB,
+            Target_Register::VC => 0xC,
+            Target_Register::VD => 0xD,
+            Target_Register::VE => 0xE,
+            Target_Register::VF => 0xF,
+            _ => 0x0, // TODO: Handle I and PC
+        }
+    }
+}
+
+impl fmt::Display for Target_Register {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // Variant names are already the conventional register names
+        write!(f, "{:?}", self)
+    }
+}
+
+const FONT_BASE: u16 = 0x50; // Font conventionally lives in the interpreter area below 0x200
+
+// 4x5 hex font for characters 0-F, 5 bytes per character
+const FONT_SET: [u8; 80] = [
+    0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
+    0x20, 0x60, 0x20, 0x20, 0x70, // 1
+    0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
+    0xF0, 0x10, 0xF0, 0x10, 0xF0, // 3
+    0x90, 0x90, 0xF0, 0x10, 0x10, // 4
+    0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
+    0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
+    0xF0, 0x10, 0x20, 0x40, 0x40, // 7
+    0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
+    0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
+    0xF0, 0x90, 0xF0, 0x90, 0x90, // A
+    0xE0, 0x90, 0xE0, 0x90, 0xE0, // B
+    0xF0, 0x80, 0x80, 0x80, 0xF0, // C
+    0xE0, 0x90, 0x90, 0x90, 0xE0, // D
+    0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
+    0xF0, 0x80, 0xF0, 0x80, 0x80, // F
+];
+
+const BIG_FONT_BASE: u16 = FONT_BASE + 80; // SCHIP's big digits go straight after the standard font
+
+// SCHIP 8x10 font for digits 0-9, 10 bytes per digit
+const BIG_FONT_SET: [u8; 100] = [
+    0x3C, 0x7E, 0xE7, 0xC3, 0xC3, 0xC3, 0xC3, 0xE7, 0x7E, 0x3C, // 0
+    0x18, 0x38, 0x58, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x3C, // 1
+    0x3E, 0x7F, 0xC3, 0x06, 0x0C, 0x18, 0x30, 0x60, 0xFF, 0xFF, // 2
+    0x3C, 0x7E, 0xC3, 0x03, 0x0E, 0x0E, 0x03, 0xC3, 0x7E, 0x3C, // 3
+    0x06, 0x0E, 0x1E, 0x36, 0x66, 0xC6, 0xFF, 0xFF, 0x06, 0x06, // 4
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFE, 0x03, 0xC3, 0x7E, 0x3C, // 5
+    0x3E, 0x7C, 0xC0, 0xC0, 0xFC, 0xFE, 0xC3, 0xC3, 0x7E, 0x3C, // 6
+    0xFF, 0xFF, 0x03, 0x06, 0x0C, 0x18, 0x30, 0x60, 0x60, 0x60, // 7
+    0x3C, 0x7E, 0xC3, 0xC3, 0x7E, 0x7E, 0xC3, 0xC3, 0x7E, 0x3C, // 8
+    0x3C, 0x7E, 0xC3, 0xC3, 0x7F, 0x3F, 0x03, 0x03, 0x3E, 0x7C, // 9
+];
+
+// Delay and sound timers count down at 60Hz regardless of how fast the CPU is cycling.
+// At a typical 600 cycles per second that works out to one timer tick every 10 cycles.
+pub const TIMER_HZ: u32 = 60;
+
+const STACK_DEPTH: usize = 16; // Subroutine nesting limit of the original interpreter
+
+// Memory layout of the original interpreter. ETI-660 ROMs start at 0x600 and XO-CHIP has 64KB,
+// both can be set up with CPU::with_layout
+pub const MEM_SIZE: usize = 4096;
+pub const PROGRAM_START: u16 = 0x200;
+
+// The display is always stored at the SCHIP high resolution, low resolution mode only uses the top left 64x32
+const DISPLAY_WIDTH: usize = 128;
+const DISPLAY_HEIGHT: usize = 64;
+
+const GHOST_FADE: u8 = 64; // Intensity an unlit pixel loses per frame with ghosting on, gone after four frames
+
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+struct Timers {
+    delay: u8,
+    sound: u8,
+}
+
+impl Timers {
+    fn new() -> Timers {
+        Timers {
+            delay: 0,
+            sound: 0,
+        }
+    }
+}
+
+#[allow(dead_code)]
+#[derive(Debug, Clone, PartialEq)]
+pub enum Instruction {
+    // X, Y represent registers
+    // N represents values
+    NOP,
+    Display, // 00E0 - Clear Screen
+    Return, // 00EE - Return from subroutine
+    LOW, // 00FE - Switch to 64x32 low resolution mode
+    HIGH, // 00FF - Switch to 128x64 high resolution mode
+    SCRD { lines: u8 }, // 00CN - Scroll display down N lines
+    SCRR, // 00FB - Scroll display right 4 columns
+    SCRL, // 00FC - Scroll display left 4 columns
+    Exit, // 00FD - Stop the interpreter
+    JUMP { address: u16 }, // 1NNN - Jump to
+    Call { address: u16 }, // 2NNN - Call subroutine
+    SKEQ { register: Target_Register, value: u8 }, // 3XNN - Skip next instruction if equal
+    SKNEQ { register: Target_Register, value: u8 }, // 4XNN - Skip next instruction if not equal
+    SKREQ { register1: Target_Register, register2: Target_Register }, // 5XY0 - Skip next instruction if X and Y registers are equal
+    SET { register: Target_Register, value: u8 }, // 6XNN - Sets X to NN
+    ADD { register: Target_Register, value: u8 }, // 7XNN - Adds NN to X, doesn't affect carry flag
+    COPYR { register1: Target_Register, register2: Target_Register }, // 8XY0 - Copy Y to X
+    OR { register1: Target_Register, register2: Target_Register }, // 8XY1 - Set X to X | Y (Bitwise OR)
+    AND { register1: Target_Register, register2: Target_Register }, // 8XY2 - Set X to X & Y (Bitwise AND)
+    XOR { register1: Target_Register, register2: Target_Register }, // 8XY3 - Set X to X ^ Y (Bitwise XOR)
+    ADDR { register1: Target_Register, register2: Target_Register }, // 8XY4 - Add Y to X, affects carry flag
+    SUBX { register1: Target_Register, register2: Target_Register }, // 8XY5 - Subtract Y from X in X, affects borrow flag
+    SHFTR { register1: Target_Register, register2: Target_Register }, // 8XY6 - Stores LSB in flag register then shifts X to the right 1
+    SUBY { register1: Target_Register, register2: Target_Register }, // 8XY7 - Subtract X from Y in X, affects borrow flag
+    SHFTL { register1: Target_Register, register2: Target_Register }, // 8XYE - Stores MSB in flag register then shifts X to the left 1
+    SKRNEQ { register1: Target_Register, register2: Target_Register }, // 9XY0 - Skip next instruction if X and Y registers are not equal
+    SETI { value: u16 }, // ANNN - Set I register to NNN
+    JMP0 { address: u16 }, // BNNN - Jump to NNN plus V0 register
+    RAND { register: Target_Register, value: u8 }, // CXNN - Set X to random number & NN
+    DRAW { register1: Target_Register, register2: Target_Register, height: u8 }, // DXYN - Draw sprite at coords X register, Y register, of N height. Width fixed at 8 pixels, except DXY0 in high resolution draws 16x16. Check documentation for this.
+    SKKEQ { register: Target_Register }, // EX9E - Skip next instruction if key stored in X is pressed
+    SKKNEQ { register: Target_Register }, // EXA1 - Skip next instruction if key stored in X isn't pressed
+    SETXD { register: Target_Register }, // FX07 - Set X to value of delay timer
+    STORE { register: Target_Register }, // FX0A - Store key press in X (Blocks until key press)
+    SETD { register: Target_Register }, // FX15 - Set delay timer to X
+    SETS { register: Target_Register }, // FX18 - Set sound timer to X
+    ADDI { register: Target_Register }, // FX1E - Add X to I
+    SPRITE { register: Target_Register }, // FX29 - Set I to address of X for character sprite (Chars 0-F in hex are represented by 4x5 font)
+    BCD { register: Target_Register }, // FX33 - Binary-Coded Decimal. Check documentation for this.
+    DUMP { register: Target_Register }, // FX55 - Dumps registers, starting from V0 to X, beginning at memory address in I
+    LOAD { register: Target_Register }, // FX65 - Fills registers, starting from V0 to X, with values beginning at memory address in I
+    DUMPF { register: Target_Register }, // FX75 - Stores registers V0 to X in the RPL user flags, X at most 7
+    LOADF { register: Target_Register }, // FX85 - Fills registers V0 to X from the RPL user flags, X at most 7
+    BigSprite { register: Target_Register }, // FX30 - Set I to address of X for big character sprite (Digits 0-9 represented by 8x10 font)
+    LoadLongI { value: u16 }, // F000 NNNN - XO-CHIP, set I to the 16 bit address in the next two bytes
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // Conventional CHIP-8 assembly mnemonics, e.g. LD V0, 0x0A or DRW V1, V2, 5
+        match self {
+            Instruction::NOP => write!(f, "NOP"),
+            Instruction::Display => write!(f, "CLS"),
+            Instruction::Return => write!(f, "RET"),
+            Instruction::LOW => write!(f, "LOW"),
+            Instruction::HIGH => write!(f, "HIGH"),
+            Instruction::SCRD { lines } => write!(f, "SCD {}", lines),
+            Instruction::SCRR => write!(f, "SCR"),
+            Instruction::SCRL => write!(f, "SCL"),
+            Instruction::Exit => write!(f, "EXIT"),
+            Instruction::JUMP { address } => write!(f, "JP 0x{:03X}", address),
+            Instruction::Call { address } => write!(f, "CALL 0x{:03X}", address),
+            Instruction::SKEQ { register, value } => write!(f, "SE {}, 0x{:02X}", register, value),
+            Instruction::SKNEQ { register, value } => write!(f, "SNE {}, 0x{:02X}", register, value),
+            Instruction::SKREQ { register1, register2 } => write!(f, "SE {}, {}", register1, register2),
+            Instruction::SET { register, value } => write!(f, "LD {}, 0x{:02X}", register, value),
+            Instruction::ADD { register, value } => write!(f, "ADD {}, 0x{:02X}", register, value),
+            Instruction::COPYR { register1, register2 } => write!(f, "LD {}, {}", register1, register2),
+            Instruction::OR { register1, register2 } => write!(f, "OR {}, {}", register1, register2),
+            Instruction::AND { register1, register2 } => write!(f, "AND {}, {}", register1, register2),
+            Instruction::XOR { register1, register2 } => write!(f, "XOR {}, {}", register1, register2),
+            Instruction::ADDR { register1, register2 } => write!(f, "ADD {}, {}", register1, register2),
+            Instruction::SUBX { register1, register2 } => write!(f, "SUB {}, {}", register1, register2),
+            Instruction::SHFTR { register1, register2 } => write!(f, "SHR {}, {}", register1, register2),
+            Instruction::SUBY { register1, register2 } => write!(f, "SUBN {}, {}", register1, register2),
+            Instruction::SHFTL { register1, register2 } => write!(f, "SHL {}, {}", register1, register2),
+            Instruction::SKRNEQ { register1, register2 } => write!(f, "SNE {}, {}", register1, register2),
+            Instruction::SETI { value } => write!(f, "LD I, 0x{:03X}", value),
+            Instruction::JMP0 { address } => write!(f, "JP V0, 0x{:03X}", address),
+            Instruction::RAND { register, value } => write!(f, "RND {}, 0x{:02X}", register, value),
+            Instruction::DRAW { register1, register2, height } => write!(f, "DRW {}, {}, {}", register1, register2, height),
+            Instruction::SKKEQ { register } => write!(f, "SKP {}", register),
+            Instruction::SKKNEQ { register } => write!(f, "SKNP {}", register),
+            Instruction::SETXD { register } => write!(f, "LD {}, DT", register),
+            Instruction::STORE { register } => write!(f, "LD {}, K", register),
+            Instruction::SETD { register } => write!(f, "LD DT, {}", register),
+            Instruction::SETS { register } => write!(f, "LD ST, {}", register),
+            Instruction::ADDI { register } => write!(f, "ADD I, {}", register),
+            Instruction::SPRITE { register } => write!(f, "LD F, {}", register),
+            Instruction::BCD { register } => write!(f, "LD B, {}", register),
+            Instruction::DUMP { register } => write!(f, "LD [I], {}", register),
+            Instruction::LOAD { register } => write!(f, "LD {}, [I]", register),
+            Instruction::DUMPF { register } => write!(f, "LD R, {}", register),
+            Instruction::LOADF { register } => write!(f, "LD {}, R", register),
+            Instruction::BigSprite { register } => write!(f, "LD HF, {}", register),
+            Instruction::LoadLongI { value } => write!(f, "LD I, long 0x{:04X}", value),
+        }
+    }
+}
+
+impl Instruction {
+    pub fn mnemonic(&self) -> &'static str {
+        // Just the operation, matching the first word of the Display output
+        match self {
+            Instruction::NOP => "NOP",
+            Instruction::Display => "CLS",
+            Instruction::Return => "RET",
+            Instruction::LOW => "LOW",
+            Instruction::HIGH => "HIGH",
+            Instruction::SCRD { .. } => "SCD",
+            Instruction::SCRR => "SCR",
+            Instruction::SCRL => "SCL",
+            Instruction::Exit => "EXIT",
+            Instruction::JUMP { .. } | Instruction::JMP0 { .. } => "JP",
+            Instruction::Call { .. } => "CALL",
+            Instruction::SKEQ { .. } | Instruction::SKREQ { .. } => "SE",
+            Instruction::SKNEQ { .. } | Instruction::SKRNEQ { .. } => "SNE",
+            Instruction::ADD { .. } | Instruction::ADDR { .. } | Instruction::ADDI { .. } => "ADD",
+            Instruction::OR { .. } => "OR",
+            Instruction::AND { .. } => "AND",
+            Instruction::XOR { .. } => "XOR",
+            Instruction::SUBX { .. } => "SUB",
+            Instruction::SHFTR { .. } => "SHR",
+            Instruction::SUBY { .. } => "SUBN",
+            Instruction::SHFTL { .. } => "SHL",
+            Instruction::RAND { .. } => "RND",
+            Instruction::DRAW { .. } => "DRW",
+            Instruction::SKKEQ { .. } => "SKP",
+            Instruction::SKKNEQ { .. } => "SKNP",
+            Instruction::SET { .. } | Instruction::COPYR { .. } | Instruction::SETI { .. }
+            | Instruction::SETXD { .. } | Instruction::STORE { .. } | Instruction::SETD { .. }
+            | Instruction::SETS { .. } | Instruction::SPRITE { .. } | Instruction::BCD { .. }
+            | Instruction::DUMP { .. } | Instruction::LOAD { .. } | Instruction::DUMPF { .. }
+            | Instruction::LOADF { .. } | Instruction::BigSprite { .. } | Instruction::LoadLongI { .. } => "LD",
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        // One line on what the instruction does, in terms of the opcode's X, Y, N and NNN fields
+        match self {
+            Instruction::NOP => "Do nothing",
+            Instruction::Display => "Clear the screen",
+            Instruction::Return => "Return from subroutine",
+            Instruction::LOW => "Switch to 64x32 low resolution",
+            Instruction::HIGH => "Switch to 128x64 high resolution",
+            Instruction::SCRD { .. } => "Scroll the display down N lines",
+            Instruction::SCRR => "Scroll the display right 4 columns",
+            Instruction::SCRL => "Scroll the display left 4 columns",
+            Instruction::Exit => "Stop the program",
+            Instruction::JUMP { .. } => "Jump to NNN",
+            Instruction::Call { .. } => "Call subroutine at NNN",
+            Instruction::SKEQ { .. } => "Skip next if VX == NN",
+            Instruction::SKNEQ { .. } => "Skip next if VX != NN",
+            Instruction::SKREQ { .. } => "Skip next if VX == VY",
+            Instruction::SET { .. } => "Set VX to NN",
+            Instruction::ADD { .. } => "Add NN to VX, VF unchanged",
+            Instruction::COPYR { .. } => "Set VX to VY",
+            Instruction::OR { .. } => "Set VX to VX OR VY",
+            Instruction::AND { .. } => "Set VX to VX AND VY",
+            Instruction::XOR { .. } => "Set VX to VX XOR VY",
+            Instruction::ADDR { .. } => "Add VY to VX, VF = carry",
+            Instruction::SUBX { .. } => "Subtract VY from VX, VF = no borrow",
+            Instruction::SHFTR { .. } => "Shift VX right by 1, VF = bit shifted out",
+            Instruction::SUBY { .. } => "Set VX to VY - VX, VF = no borrow",
+            Instruction::SHFTL { .. } => "Shift VX left by 1, VF = bit shifted out",
+            Instruction::SKRNEQ { .. } => "Skip next if VX != VY",
+            Instruction::SETI { .. } => "Set I to NNN",
+            Instruction::JMP0 { .. } => "Jump to NNN + V0",
+            Instruction::RAND { .. } => "Set VX to a random number AND NN",
+            Instruction::DRAW { .. } => "Draw N rows of sprite data from I at VX, VY, VF = collision",
+            Instruction::SKKEQ { .. } => "Skip next if the key in VX is pressed",
+            Instruction::SKKNEQ { .. } => "Skip next if the key in VX isn't pressed",
+            Instruction::SETXD { .. } => "Set VX to the delay timer",
+            Instruction::STORE { .. } => "Wait for a key press and store it in VX",
+            Instruction::SETD { .. } => "Set the delay timer to VX",
+            Instruction::SETS { .. } => "Set the sound timer to VX",
+            Instruction::ADDI { .. } => "Add VX to I",
+            Instruction::SPRITE { .. } => "Set I to the font sprite for the digit in VX",
+            Instruction::BCD { .. } => "Store the decimal digits of VX at I, I + 1 and I + 2",
+            Instruction::DUMP { .. } => "Store V0 to VX in memory starting at I",
+            Instruction::LOAD { .. } => "Load V0 to VX from memory starting at I",
+            Instruction::DUMPF { .. } => "Store V0 to VX in the RPL flags",
+            Instruction::LOADF { .. } => "Load V0 to VX from the RPL flags",
+            Instruction::BigSprite { .. } => "Set I to the big font sprite for the digit in VX",
+            Instruction::LoadLongI { .. } => "Set I to the 16 bit address in the next two bytes",
+        }
+    }
+
+    pub fn category(&self) -> &'static str {
+        // Broad groups for profiling, see CPU::stats
+        match self {
+            Instruction::NOP | Instruction::Return | Instruction::JUMP { .. } | Instruction::Call { .. }
+            | Instruction::SKEQ { .. } | Instruction::SKNEQ { .. } | Instruction::SKREQ { .. }
+            | Instruction::SKRNEQ { .. } | Instruction::JMP0 { .. } | Instruction::Exit => "flow",
+            Instruction::SET { .. } | Instruction::COPYR { .. } => "move",
+            Instruction::ADD { .. } | Instruction::ADDR { .. } | Instruction::SUBX { .. } | Instruction::SUBY { .. }
+            | Instruction::ADDI { .. } | Instruction::BCD { .. } => "arithmetic",
+            Instruction::OR { .. } | Instruction::AND { .. } | Instruction::XOR { .. }
+            | Instruction::SHFTR { .. } | Instruction::SHFTL { .. } => "logic",
+            Instruction::RAND { .. } => "random",
+            Instruction::Display | Instruction::LOW | Instruction::HIGH | Instruction::SCRD { .. }
+            | Instruction::SCRR | Instruction::SCRL | Instruction::DRAW { .. } => "display",
+            Instruction::SKKEQ { .. } | Instruction::SKKNEQ { .. } | Instruction::STORE { .. } => "input",
+            Instruction::SETXD { .. } | Instruction::SETD { .. } | Instruction::SETS { .. } => "timers",
+            Instruction::SETI { .. } | Instruction::SPRITE { .. } | Instruction::DUMP { .. } | Instruction::LOAD { .. }
+            | Instruction::DUMPF { .. } | Instruction::LOADF { .. } | Instruction::BigSprite { .. }
+            | Instruction::LoadLongI { .. } => "memory",
+        }
+    }
+
+    pub fn to_opcode(&self) -> Option<u16> {
+        // Inverse of parse_opcode. None when a field doesn't fit its slot in the opcode,
+        // like an address past 0xFFF or I and PC used as X or Y, since those can't come out of decoding
+        fn reg(register: &Target_Register) -> Option<u16> {
+            match register {
+                Target_Register::I | Target_Register::PC => None,
+                _ => Some(Target_Register::register_to_u8(register) as u16),
+            }
+        }
+        fn xy(register1: &Target_Register, register2: &Target_Register) -> Option<u16> {
+            Some(reg(register1)? << 8 | reg(register2)? << 4)
+        }
+        fn addr(address: u16) -> Option<u16> {
+            if address <= 0xFFF { Some(address) } else { None }
+        }
+
+        let opcode = match self {
+            Instruction::NOP => 0x0000,
+            Instruction::Display => 0x00E0,
+            Instruction::Return => 0x00EE,
+            Instruction::LOW => 0x00FE,
+            Instruction::HIGH => 0x00FF,
+            Instruction::SCRD { lines } if *lines <= 0xF => 0x00C0 | *lines as u16,
+            Instruction::SCRD { .. } => return None,
+            Instruction::SCRR => 0x00FB,
+            Instruction::SCRL => 0x00FC,
+            Instruction::Exit => 0x00FD,
+            Instruction::JUMP { address } => 0x1000 | addr(*address)?,
+            Instruction::Call { address } => 0x2000 | addr(*address)?,
+            Instruction::SKEQ { register, value } => 0x3000 | reg(register)? << 8 | *value as u16,
+            Instruction::SKNEQ { register, value } => 0x4000 | reg(register)? << 8 | *value as u16,
+            Instruction::SKREQ { register1, register2 } => 0x5000 | xy(register1, register2)?,
+            Instruction::SET { register, value } => 0x6000 | reg(register)? << 8 | *value as u16,
+            Instruction::ADD { register, value } => 0x7000 | reg(register)? << 8 | *value as u16,
+            Instruction::COPYR { register1, register2 } => 0x8000 | xy(register1, register2)?,
+            Instruction::OR { register1, register2 } => 0x8001 | xy(register1, register2)?,
+            Instruction::AND { register1, register2 } => 0x8002 | xy(register1, register2)?,
+            Instruction::XOR { register1, register2 } => 0x8003 | xy(register1, register2)?,
+            Instruction::ADDR { register1, register2 } => 0x8004 | xy(register1, register2)?,
+            Instruction::SUBX { register1, register2 } => 0x8005 | xy(register1, register2)?,
+            Instruction::SHFTR { register1, register2 } => 0x8006 | xy(register1, register2)?,
+            Instruction::SUBY { register1, register2 } => 0x8007 | xy(register1, register2)?,
+            Instruction::SHFTL { register1, register2 } => 0x800E | xy(register1, register2)?,
+            Instruction::SKRNEQ { register1, register2 } => 0x9000 | xy(register1, register2)?,
+            Instruction::SETI { value } => 0xA000 | addr(*value)?,
+            Instruction::JMP0 { address } => 0xB000 | addr(*address)?,
+            Instruction::RAND { register, value } => 0xC000 | reg(register)? << 8 | *value as u16,
+            Instruction::DRAW { register1, register2, height } if *height <= 0xF => 0xD000 | xy(register1, register2)? | *height as u16,
+            Instruction::DRAW { .. } => return None,
+            Instruction::SKKEQ { register } => 0xE09E | reg(register)? << 8,
+            Instruction::SKKNEQ { register } => 0xE0A1 | reg(register)? << 8,
+            Instruction::SETXD { register } => 0xF007 | reg(register)? << 8,
+            Instruction::STORE { register } => 0xF00A | reg(register)? << 8,
+            Instruction::SETD { register } => 0xF015 | reg(register)? << 8,
+            Instruction::SETS { register } => 0xF018 | reg(register)? << 8,
+            Instruction::ADDI { register } => 0xF01E | reg(register)? << 8,
+            Instruction::SPRITE { register } => 0xF029 | reg(register)? << 8,
+            Instruction::BCD { register } => 0xF033 | reg(register)? << 8,
+            Instruction::DUMP { register } => 0xF055 | reg(register)? << 8,
+            Instruction::LOAD { register } => 0xF065 | reg(register)? << 8,
+            Instruction::DUMPF { register } => 0xF075 | reg(register)? << 8,
+            Instruction::LOADF { register } => 0xF085 | reg(register)? << 8,
+            Instruction::BigSprite { register } => 0xF030 | reg(register)? << 8,
+            // Takes two words, the F000 and then the address, so it has no single opcode
+            Instruction::LoadLongI { .. } => return None,
+        };
+        Some(opcode)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum DecodeErrorKind {
+    UnknownOpcode, // Doesn't map to any instruction
+    UnknownSubOpcode { sub_opcode: u8 }, // The high nibble picks a family like 8XY_ or FX__, but the low bits don't name one of its instructions
+    UnsupportedInstruction, // Valid CHIP-8 but not something this interpreter runs, like 0NNN machine code calls
+}
+
+#[derive(Debug)]
+pub struct DecodeError {
+    pub kind: DecodeErrorKind,
+    pub opcode: u16, // Raw opcode that couldn't be decoded
+    pub pc: u16, // Address the opcode was fetched from
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.kind {
+            DecodeErrorKind::UnknownOpcode => write!(f, "Unexpected opcode: {:04X} at {:03X}", self.opcode, self.pc),
+            DecodeErrorKind::UnknownSubOpcode { sub_opcode } => write!(f, "Unknown sub-opcode {:X} in {:04X} at {:03X}", sub_opcode, self.opcode, self.pc),
+            DecodeErrorKind::UnsupportedInstruction => write!(f, "Unsupported instruction: {:04X} at {:03X}", self.opcode, self.pc),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+#[derive(Debug)]
+pub enum ExecError {
+    Decode(DecodeError), // Fetched opcode couldn't be turned into an instruction
+    StackUnderflow { pc: u16 }, // Return with nothing on the call stack
+    StackOverflow { pc: u16 }, // Call nested deeper than STACK_DEPTH
+    Misaligned { pc: u16 }, // PC landed on an odd address with require_alignment on
+    IndexOutOfRange { pc: u16, address: usize }, // DRAW, DUMP or LOAD reached past the end of memory with strict_index on
+    AddressOverflow { pc: u16, address: usize }, // I went past 0xFFFF, or PC past the end of memory
+}
+
+impl From<DecodeError> for ExecError {
+    fn from(e: DecodeError) -> ExecError {
+        ExecError::Decode(e)
+    }
+}
+
+impl fmt::Display for ExecError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ExecError::Decode(e) => write!(f, "{}", e),
+            ExecError::StackUnderflow { pc } => write!(f, "Return with an empty call stack at {:03X}", pc),
+            ExecError::StackOverflow { pc } => write!(f, "Call nested more than {} deep at {:03X}", STACK_DEPTH, pc),
+            ExecError::Misaligned { pc } => write!(f, "Instruction fetch from odd address {:03X}", pc),
+            ExecError::IndexOutOfRange { pc, address } => write!(f, "Memory access at {:03X} is past the end of memory at {:03X}", address, pc),
+            ExecError::AddressOverflow { pc, address } => write!(f, "Address {:03X} is out of range at {:03X}", address, pc),
+        }
+    }
+}
+
+impl std::error::Error for ExecError {}
+
+// Why CPU::run or run_with came back without an error
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RunStatus {
+    Halted, // The program jumped to itself or exited
+    BudgetExhausted, // Ran the max_cycles set with CPU::set_max_cycles without halting
+    Quit, // The frontend asked to stop, only from run_with
+}
+
+// Behaviors that differ between CHIP-8 interpreters. Everything off matches modern interpreters
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Quirks {
+    pub shift: bool, // COSMAC VIP: SHFTR/SHFTL shift register2 into register1 instead of shifting register1 in place
+    pub memory_increment: bool, // COSMAC VIP: DUMP and LOAD leave I incremented by X + 1
+    pub jump: bool, // SCHIP: BXNN jumps to XNN plus VX instead of NNN plus V0
+    pub vf_reset: bool, // COSMAC VIP: OR, AND and XOR reset VF to 0
+    pub addi_overflow: bool, // Amiga interpreter: ADDI sets VF when I goes past 0x0FFF
+    pub schip: bool, // SCHIP: 00CN, 00FB and 00FC scroll the display, 00FD exits, FX30 points I at the big font and FX75/FX85 use the RPL flags instead of being unsupported
+    pub clip_sprites: bool, // COSMAC VIP: DRAW drops pixels past the right and bottom edges instead of wrapping them
+    pub require_alignment: bool, // Not an interpreter quirk, fails on fetching from an odd PC to catch bad jumps early
+    pub strict_index: bool, // Not an interpreter quirk, fails when DRAW, DUMP or LOAD would read or write past the end of memory instead of wrapping
+    pub display_wait: bool, // COSMAC VIP: DRAW waits for the next display interrupt, so run_frame, run and run_with manage one DRAW per frame
+    pub key_release_on_fx0a: bool, // COSMAC VIP: STORE only finishes once the pressed key is let go again
+    pub xochip: bool, // XO-CHIP: F000 NNNN loads a 16 bit address into I, and skips step over all four bytes of it
+    pub watch_code_writes: bool, // Not an interpreter quirk, traces and counts memory writes into the program at or below PC to find self-modifying code
+}
+
+impl Quirks {
+    pub fn cosmac_vip() -> Quirks {
+        Quirks {
+            shift: true,
+            memory_increment: true,
+            jump: false,
+            vf_reset: true,
+            addi_overflow: false,
+            schip: false,
+            clip_sprites: true,
+            require_alignment: false,
+            strict_index: false,
+            display_wait: true,
+            key_release_on_fx0a: true,
+            xochip: false,
+            watch_code_writes: false,
+        }
+    }
+
+    pub fn schip() -> Quirks {
+        // SCHIP 1.1 on the HP48: shifts in place, BXNN jumps, sprites clip and the extra instructions are on
+        Quirks {
+            shift: false,
+            memory_increment: false,
+            jump: true,
+            vf_reset: false,
+            addi_overflow: false,
+            schip: true,
+            clip_sprites: true,
+            require_alignment: false,
+            strict_index: false,
+            display_wait: false,
+            key_release_on_fx0a: false,
+            xochip: false,
+            watch_code_writes: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Platform {
+    Chip8, // Original COSMAC VIP CHIP-8
+    Schip, // SUPER-CHIP 1.1
+}
+
+// detect_variant's best guess at what a ROM was written for
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Variant {
+    pub platform: Platform,
+    pub quirks: Quirks, // Preset to run it with
+    pub confidence: f32, // 0.0 to 1.0
+}
+
+// Host keyboard to CHIP-8 keypad translation, shared by the frontends so they all agree
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyMap {
+    keys: [char; 16], // Host key for each keypad key 0-F, stored lowercase
+}
+
+impl KeyMap {
+    pub fn qwerty() -> KeyMap {
+        // The COSMAC VIP keypad laid over the left side of a QWERTY keyboard
+        //   1 2 3 C      1 2 3 4
+        //   4 5 6 D  ->  Q W E R
+        //   7 8 9 E      A S D F
+        //   A 0 B F      Z X C V
+        KeyMap {
+            keys: [
+                'x', '1', '2', '3', 'q', 'w', 'e', 'a',
+                's', 'd', 'z', 'c', '4', 'r', 'f', 'v',
+            ],
+        }
+    }
+
+    pub fn set(&mut self, keypad_key: u8, host_key: char) {
+        // Keys outside of 0-F don't exist on the keypad and are ignored, like set_key
+        if let Some(k) = self.keys.get_mut(keypad_key as usize) {
+            *k = host_key.to_ascii_lowercase();
+        };
+    }
+
+    pub fn map_key(&self, host_key: char) -> Option<u8> {
+        // Letters match either case so caps lock doesn't break input
+        let host_key = host_key.to_ascii_lowercase();
+        self.keys.iter().position(|&k| k == host_key).map(|k| k as u8)
+    }
+}
+
+impl Default for KeyMap {
+    fn default() -> KeyMap {
+        KeyMap::qwerty()
+    }
+}
+
+// Counters for profiling a ROM, kept up to date by step
+#[derive(Debug, Clone, Default)]
+pub struct Stats {
+    pub cycles: u64, // Instructions executed
+    pub categories: HashMap<&'static str, u64>, // Executed instructions by Instruction::category
+    pub code_writes: u64, // Writes into already run program memory, only counted with the watch_code_writes quirk
+}
+
+// Keypad changes captured by CPU::start_recording, replayed with CPU::play_recording.
+// Together with a fixed RAND seed a replay runs exactly like the recorded session
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+pub struct InputLog {
+    events: Vec<(u64, u8, bool)>, // Cycles since recording started, key, pressed
+}
+
+impl InputLog {
+    pub fn events(&self) -> &[(u64, u8, bool)] {
+        &self.events
+    }
+}
+
+// What a single step executed, for debuggers and scripts that want more than debug_cycle's printing
+#[derive(Debug, Clone)]
+pub struct StepInfo {
+    pub pc: u16, // Address the opcode was fetched from
+    pub opcode: u16,
+    pub instruction: Instruction,
+    pub display_changed: bool, // The instruction cleared, drew to, scrolled or resized the display
+    pub executed: bool, // False when the pre_exec hook skipped the instruction
+}
+
+// Chainable setup for a CPU, started from CPU::builder. Anything left unset gets CPU::new's default
+#[derive(Debug, Clone, Default)]
+pub struct CpuBuilder {
+    quirks: Quirks,
+    seed: Option<u64>, // Seeded from entropy when None
+    memory_size: Option<usize>,
+    program_start: Option<u16>,
+    font: Option<(Vec<u8>, u16)>, // Glyphs and the address they go at
+}
+
+// Monochrome display, always stored at the SCHIP high resolution with the active mode on top.
+// Pixels outside the active area read as off and ignore writes
+#[derive(Clone, PartialEq)]
+pub struct Framebuffer {
+    pixels: [[bool; DISPLAY_WIDTH]; DISPLAY_HEIGHT], // Indexed [y][x]
+    hires: bool, // 128x64 when set, otherwise 64x32 in the top left
+}
+
+impl Framebuffer {
+    fn new() -> Framebuffer {
+        Framebuffer {
+            pixels: [[false; DISPLAY_WIDTH]; DISPLAY_HEIGHT],
+            hires: false,
+        }
+    }
+
+    pub fn resolution(&self) -> (usize, usize) {
+        // Width and height of the active display mode
+        if self.hires { (DISPLAY_WIDTH, DISPLAY_HEIGHT) } else { (64, 32) }
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> bool {
+        let (width, height) = self.resolution();
+        x < width && y < height && self.pixels[y][x]
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, lit: bool) {
+        let (width, height) = self.resolution();
+        if x < width && y < height {
+            self.pixels[y][x] = lit;
+        };
+    }
+
+    pub fn xor_pixel(&mut self, x: usize, y: usize) -> bool {
+        // Flips the pixel and returns what it was before, so true means a lit pixel got turned off
+        let was_lit = self.get(x, y);
+        self.set(x, y, !was_lit);
+        was_lit
+    }
+
+    pub fn rows(&self) -> impl Iterator<Item = &[bool]> + '_ {
+        // The active area only, top row first
+        let (width, height) = self.resolution();
+        self.pixels.iter().take(height).map(move |row| &row[..width])
+    }
+
+    fn clear(&mut self) {
+        self.pixels = [[false; DISPLAY_WIDTH]; DISPLAY_HEIGHT];
+    }
+
+    fn hex_rows(&self) -> Vec<String> {
+        // One hex digit per four pixels, leftmost pixel in the highest bit. The widths are always multiples of 4
+        self.rows().map(|row| {
+            row.chunks(4).map(|pixels| {
+                let nibble = pixels.iter().fold(0, |acc, &lit| acc << 1 | lit as u32);
+                std::char::from_digit(nibble, 16).unwrap_or('0')
+            }).collect()
+        }).collect()
+    }
+
+    fn to_terminal(&self) -> String {
+        // Clear the terminal and move the cursor home with ANSI escapes, then draw lit pixels as full blocks
+        let mut out = String::from("\x1b[2J\x1b[H");
+        for row in self.rows() {
+            for &pixel in row {
+                out.push(if pixel { '█' } else { ' ' });
+            };
+            out.push('\n');
+        };
+        out
+    }
+}
+
+impl fmt::Display for Framebuffer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // ASCII art, # for lit pixels and . for the rest
+        for row in self.rows() {
+            let line: String = row.iter().map(|&lit| if lit { '#' } else { '.' }).collect();
+            writeln!(f, "{}", line)?;
+        };
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Framebuffer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // A few hundred bools are unreadable, so rows are printed as hex instead
+        let (width, height) = self.resolution();
+        f.debug_struct("Framebuffer")
+            .field("width", &width)
+            .field("height", &height)
+            .field("rows", &self.hex_rows())
+            .finish()
+    }
+}
+
+// Callbacks have to be Send as well, so a CPU can be moved to another thread (one per core when fuzzing)
+type PreExecHook = Box<dyn FnMut(&Instruction, &Registers) -> bool + Send>;
+
+pub struct CPU {
+    registers: Registers,
+    memory: Vec<u8>, // mem_size bytes, fixed once the CPU is created
+    program_start: u16, // Where ROMs are loaded and execution begins
+    rom_len: usize, // Bytes the last loaded ROM took up from program_start
+    font: Vec<u8>, // 5 bytes per hex digit 0-F, FONT_SET unless load_font replaced it
+    font_base: u16, // Where the font sits in memory, SPRITE points I in here
+    stack: Vec<u16>,
+    timers: Timers,
+    display: Framebuffer,
+    display_dirty: Cell<bool>, // Set when the display changes, cleared when the frontend reads the framebuffer
+    draw_collided: bool, // VF as the last DRAW left it, before anything else could overwrite it
+    intensity: Vec<u8>, // Per pixel brightness laid out like the display, empty unless ghosting is on
+    keys: [bool; 16], // Hex keypad 0-F, true while held down
+    pending_key: Option<u8>, // Key STORE saw pressed and is waiting on to be released, with key_release_on_fx0a
+    rpl_flags: [u8; 8], // HP48 RPL user flags, SCHIP's only storage outside of memory
+    rng: StdRng, // Source for RAND, seeded from entropy unless a seed is given
+    halted: bool, // Set when the ROM jumps to itself, which is how most programs idle once they're done, or runs SCHIP's exit
+    owed: u64, // Cycle budget carried between frames so rates that don't divide by 60 still average out
+    max_cycles: Option<u64>, // Most instructions a single run may execute, unlimited when None
+    quirks: Quirks,
+    sound_callback: Option<Box<dyn FnMut(bool) + Send>>, // Told when the beep starts (true) and stops (false)
+    trace: Option<BufWriter<fs::File>>, // Executed instructions get logged here when tracing is on
+    pre_exec: Option<PreExecHook>, // Sees every instruction first and can skip it by returning false
+    stats: Stats,
+    recording: Option<(u64, InputLog)>, // Cycle count the recording started at and the keys captured since
+    playback: VecDeque<(u64, u8, bool)>, // Keys still to replay, timed by stats.cycles
+}
+
+// A copy of everything that changes while a ROM runs, used for debugger undo and save states.
+// Serde can't handle the fixed size memory and display arrays directly, so snapshots
+// flatten them into Vecs and save states check the lengths again on the way back in
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+pub struct Snapshot {
+    registers: Registers,
+    memory: Vec<u8>,
+    stack: Vec<u16>,
+    timers: Timers,
+    display: Vec<bool>, // Row by row, DISPLAY_WIDTH pixels per row
+    hires: bool,
+    keys: [bool; 16],
+    rpl_flags: [u8; 8],
+}
+
+// Machine state laid out for external debuggers by to_json. Unlike a save state it's meant to be read,
+// so memory is cut down to the ranges that aren't zero and each display row becomes one hex bitmask
+#[cfg(feature = "serialize")]
+#[derive(Serialize)]
+struct DebugState {
+    registers: Registers,
+    stack: Vec<u16>,
+    timers: Timers,
+    memory: Vec<MemoryRange>,
+    width: usize,
+    height: usize,
+    display: Vec<String>, // Active resolution only, leftmost pixel in the highest bit
+}
+
+#[cfg(feature = "serialize")]
+#[derive(Serialize)]
+struct MemoryRange {
+    start: u16,
+    bytes: Vec<u8>,
+}
+
+pub fn disassemble(bytes: &[u8]) -> Vec<(u16, Result<Instruction, DecodeError>)> {
+    // Walks a ROM two bytes at a time as if it was loaded at PROGRAM_START. Words that don't decode
+    // (usually sprite data mixed in with the code) come back as errors so every word has an entry.
+    // Addresses wrap past 0xFFFF, a ROM too big to load still gets listed rather than overflowing
+    bytes.chunks(2).enumerate().map(|(i, word)| {
+        let address = PROGRAM_START.wrapping_add((i * 2) as u16);
+        let opcode = (word[0] as u16) << 8 | *word.get(1).unwrap_or(&0) as u16;
+        let decoded = CPU::parse_opcode(opcode).map_err(|e| DecodeError { pc: address, ..e });
+        (address, decoded)
+    }).collect()
+}
+
+pub fn detect_variant(bytes: &[u8]) -> Variant {
+    // Looks for instructions only SCHIP has. Sprite data can decode as anything, so each kind of
+    // SCHIP instruction found adds to the confidence rather than one match settling it
+    let mut scroll = false;
+    let mut resolution = false;
+    let mut rpl = false;
+    let mut exit = false;
+    for instruction in disassemble(bytes).into_iter().filter_map(|(_, decoded)| decoded.ok()) {
+        match instruction {
+            Instruction::SCRD { .. } | Instruction::SCRR | Instruction::SCRL => scroll = true,
+            Instruction::LOW | Instruction::HIGH => resolution = true,
+            Instruction::DUMPF { .. } | Instruction::LOADF { .. } => rpl = true,
+            Instruction::Exit => exit = true,
+            _ => (),
+        };
+    };
+
+    let kinds = [scroll, resolution, rpl, exit].iter().filter(|&&x| x).count();
+    if kinds == 0 {
+        // Nothing SCHIP specific, though plenty of CHIP-8 ROMs would run the same either way
+        return Variant { platform: Platform::Chip8, quirks: Quirks::cosmac_vip(), confidence: 0.5 };
+    };
+    Variant { platform: Platform::Schip, quirks: Quirks::schip(), confidence: (0.4 + 0.2 * kinds as f32).min(0.95) }
+}
+
+// How fuzz_rom's run ended
+#[derive(Debug)]
+pub enum FuzzResult {
+    Halted { cycles: usize }, // Jumped to itself or exited
+    Error { cycles: usize, error: ExecError }, // Stopped by an ExecError after this many good cycles
+    CycleLimit, // Still running after max_cycles
+    TooLarge, // Didn't fit in memory, so never ran
+}
+
+pub fn fuzz_rom(bytes: &[u8], max_cycles: usize) -> FuzzResult {
+    // Runs arbitrary bytes as a ROM and reports how it ended. Any panic in here is an interpreter bug.
+    // The seed is fixed so a result can always be reproduced from the same bytes
+    let mut cpu = CPU::with_seed(0);
+    if cpu.load_rom_bytes(bytes).is_err() {
+        return FuzzResult::TooLarge;
+    };
+    for cycles in 0..max_cycles {
+        if cpu.is_halted() {
+            return FuzzResult::Halted { cycles };
+        };
+        if let Err(error) = cpu.cycle() {
+            return FuzzResult::Error { cycles, error };
+        };
+    };
+    if cpu.is_halted() {
+        return FuzzResult::Halted { cycles: max_cycles };
+    };
+    FuzzResult::CycleLimit
+}
+
+// The I/O side of running a ROM, so every frontend can share run_with's timing
+pub trait Frontend {
+    fn poll_input(&mut self) -> Vec<(u8, bool)>; // Keypad changes since the last poll, as key 0-F and pressed
+    fn present(&mut self, fb: &Framebuffer); // Only called when the display changed
+    fn beep(&mut self, on: bool); // Called when the buzzer starts and stops
+
+    fn quit_requested(&self) -> bool {
+        // Checked every frame, for frontends that can be closed
+        false
+    }
+}
+
+// Draws to the terminal with ANSI escapes and rings the bell for the buzzer. It can't read
+// individual key presses from a plain terminal, so the keypad is never pressed
+#[derive(Debug, Default)]
+pub struct TerminalFrontend;
+
+impl Frontend for TerminalFrontend {
+    fn poll_input(&mut self) -> Vec<(u8, bool)> {
+        Vec::new()
+    }
+
+    fn present(&mut self, fb: &Framebuffer) {
+        print!("{}", fb.to_terminal());
+    }
+
+    fn beep(&mut self, on: bool) {
+        if on {
+            print!("\x07");
+        };
+    }
+}
+
+pub fn run_with<F: Frontend>(cpu: &mut CPU, frontend: &mut F, cycles_per_second: u32) -> Result<RunStatus, ExecError> {
+    // CPU::run's frame pacing and max_cycles budget, with input, sound and drawing handed to the frontend each frame.
+    // Stops when the frontend asks to quit, the program halts or the budget runs out, after the last frame is presented
+    let frame = Duration::from_secs(1) / TIMER_HZ;
+    let mut next_frame = Instant::now() + frame;
+    let mut beeping = false;
+    let mut remaining = cpu.max_cycles.unwrap_or(u64::MAX);
+
+    let result = loop {
+        for (key, pressed) in frontend.poll_input() {
+            cpu.set_key(key, pressed);
+        };
+        if frontend.quit_requested() {
+            break Ok(RunStatus::Quit);
+        };
+
+        match cpu.frame(cycles_per_second, remaining) {
+            Ok(steps) => remaining -= steps,
+            Err(e) => break Err(e),
+        };
+
+        if cpu.is_beeping() != beeping {
+            beeping = !beeping;
+            frontend.beep(beeping);
+        };
+        if cpu.display_dirty() {
+            frontend.present(cpu.framebuffer());
+        };
+        if cpu.is_halted() {
+            break Ok(RunStatus::Halted);
+        };
+        if remaining == 0 {
+            break Ok(RunStatus::BudgetExhausted);
+        };
+
+        let now = Instant::now();
+        if next_frame > now {
+            thread::sleep(next_frame - now);
+            next_frame += frame;
+        } else {
+            next_frame = now + frame;
+        };
+    };
+
+    // Nothing ticks the sound timer any more, so don't leave the frontend beeping
+    if beeping {
+        frontend.beep(false);
+    };
+    result
+}
+
+#[allow(dead_code)]
+fn assert_cpu_is_send() {
+    // Never called, it just stops compiling if a CPU field stops being Send
+    fn is_send<T: Send>() {}
+    is_send::<CPU>();
+}
+
+impl Default for CPU {
+    fn default() -> CPU {
+        CPU::new(Quirks::default())
+    }
+}
+
+impl CpuBuilder {
+    pub fn quirks(mut self, quirks: Quirks) -> CpuBuilder {
+        self.quirks = quirks;
+        self
+    }
+
+    pub fn seed(mut self, seed: u64) -> CpuBuilder {
+        self.seed = Some(seed);
+        self
+    }
+
+    pub fn memory_size(mut self, memory_size: usize) -> CpuBuilder {
+        self.memory_size = Some(memory_size);
+        self
+    }
+
+    pub fn program_start(mut self, program_start: u16) -> CpuBuilder {
+        self.program_start = Some(program_start);
+        self
+    }
+
+    pub fn font(mut self, font: &[u8], base: u16) -> CpuBuilder {
+        self.font = Some((font.to_vec(), base));
+        self
+    }
+
+    pub fn build(self) -> io::Result<CPU> {
+        // Same layout rules as CPU::with_layout, but a bad layout is an error here rather than a panic,
+        // and so is a font that doesn't fit like in load_font
+        let memory_size = self.memory_size.unwrap_or(MEM_SIZE);
+        let program_start = self.program_start.unwrap_or(PROGRAM_START);
+        if memory_size > 0x10000 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                format!("memory size {} is larger than the 16 bit address space", memory_size)));
+        };
+        if (program_start as usize) < BIG_FONT_BASE as usize + BIG_FONT_SET.len() || program_start as usize >= memory_size {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                format!("program start 0x{:X} has to be above the fonts and inside {} bytes of memory", program_start, memory_size)));
+        };
+        let mut cpu = CPU::with_layout(self.quirks, memory_size, program_start);
+        if let Some(seed) = self.seed {
+            cpu.reseed(seed);
+        };
+        if let Some((font, base)) = self.font {
+            cpu.load_font(&font, base)?;
+        };
+        Ok(cpu)
+    }
+}
+
+#[allow(non_snake_case)]
+#[allow(dead_code)]
+impl CPU {
+    pub fn load_rom(&mut self, rom: &str) -> Result<&str, io::Error> {
+        match fs::read(rom.trim()) {
+            Ok(x) => {
+                self.load_rom_bytes(&x)?;
+                Ok("ROM loaded successfully.")
+            },
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn mem_size(&self) -> usize {
+        self.memory.len()
+    }
+
+    pub fn program_start(&self) -> u16 {
+        self.program_start
+    }
+
+    pub fn read_memory(&self, addr: u16) -> Option<u8> {
+        self.memory.get(addr as usize).cloned()
+    }
+
+    #[allow(clippy::result_unit_err)] // The only way to fail is an address past mem_size
+    pub fn write_memory(&mut self, addr: u16, value: u8) -> Result<(), ()> {
+        match self.memory.get_mut(addr as usize) {
+            Some(byte) => {
+                *byte = value;
+                Ok(())
+            },
+            None => Err(()),
+        }
+    }
+
+    pub fn load_rom_reader<R: Read>(&mut self, reader: &mut R) -> io::Result<usize> {
+        // Anything readable works, e.g. stdin or a ROM embedded with include_bytes!
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+        self.load_rom_bytes(&bytes)?;
+        Ok(bytes.len())
+    }
+
+    pub fn load_rom_bytes(&mut self, bytes: &[u8]) -> io::Result<()> {
+        // Refuse ROMs that would run off the end of memory rather than loading part of one
+        let start = self.program_start as usize;
+        if bytes.len() > self.memory.len() - start {
+            return Err(io::Error::new(io::ErrorKind::InvalidData,
+                format!("ROM is {} bytes but only {} fit in memory", bytes.len(), self.memory.len() - start)));
+        };
+        self.memory[start..start + bytes.len()].copy_from_slice(bytes);
+        self.rom_len = bytes.len();
+        self.registers.PC = self.program_start; //Programs begin at this address
+        self.halted = false;
+        Ok(())
+    }
+
+    pub fn instructions(&self) -> impl Iterator<Item = (u16, Result<Instruction, DecodeError>)> + '_ {
+        // Decodes the loaded ROM as it sits in memory now, two bytes at a time from program_start
+        // (four for XO-CHIP's F000 NNNN). Same rules as execution, so instructions the quirks turn off come back as errors
+        let end = self.program_start as usize + self.rom_len;
+        let mut address = self.program_start as usize;
+        std::iter::from_fn(move || {
+            if address >= end {
+                return None;
+            };
+            let pc = address as u16;
+            let decoded = self.decode(self.read_word(pc), pc);
+            address += if let Ok(Instruction::LoadLongI { .. }) = decoded { 4 } else { 2 };
+            Some((pc, decoded))
+        })
+    }
+    
+    fn fetch_instruction(&mut self) -> Result<u16, ExecError> {
+       // PC doesn't wrap around to the start of memory, running off the end is an error
+       let pc = self.registers.PC as usize;
+       if pc + 2 >= self.memory.len() {
+           return Err(ExecError::AddressOverflow { pc: self.registers.PC, address: pc + 2 });
+       };
+       let opcode = (self.memory[pc] as u16) << 8 | self.memory[pc + 1] as u16;
+       self.registers.PC = (pc + 2) as u16;
+       Ok(opcode)
+    }
+
+    pub fn new(quirks: Quirks) -> CPU {
+        CPU::with_layout(quirks, MEM_SIZE, PROGRAM_START)
+    }
+
+    pub fn builder() -> CpuBuilder {
+        CpuBuilder::default()
+    }
+
+    pub fn with_seed(seed: u64) -> CPU {
+        // Same RAND results every run, for tests and replays
+        let mut cpu = CPU::new(Quirks::default());
+        cpu.reseed(seed);
+        cpu
+    }
+
+    pub fn reseed(&mut self, seed: u64) {
+        self.rng = StdRng::seed_from_u64(seed);
+    }
+
+    pub fn with_layout(quirks: Quirks, mem_size: usize, program_start: u16) -> CPU {
+        // Addresses are 16 bit, so memory can't go past 64KB. The font has to fit below the program
+        assert!(mem_size <= 0x10000, "mem_size {} is larger than the 16 bit address space", mem_size);
+        assert!(BIG_FONT_BASE as usize + BIG_FONT_SET.len() <= program_start as usize && (program_start as usize) < mem_size,
+            "program_start 0x{:X} has to be above the fonts and inside memory", program_start);
+
+        let mut cpu = CPU {
+            registers: Registers::new(),
+            memory: vec![0u8; mem_size],
+            program_start,
+            rom_len: 0,
+            font: FONT_SET.to_vec(),
+            font_base: FONT_BASE,
+            stack: Vec::with_capacity(STACK_DEPTH),
+            timers: Timers::new(),
+            display: Framebuffer::new(),
+            display_dirty: Cell::new(true),
+            draw_collided: false,
+            intensity: Vec::new(),
+            keys: [false; 16],
+            pending_key: None,
+            rpl_flags: [0u8; 8],
+            rng: StdRng::from_entropy(),
+            halted: false,
+            owed: 0,
+            max_cycles: None,
+            quirks,
+            sound_callback: None,
+            trace: None,
+            pre_exec: None,
+            stats: Stats::default(),
+            recording: None,
+            playback: VecDeque::new(),
+        };
+        cpu.load_font_set();
+        cpu
+    }
+
+    fn load_font_set(&mut self) {
+        let base = BIG_FONT_BASE as usize;
+        self.memory[base..base + BIG_FONT_SET.len()].copy_from_slice(&BIG_FONT_SET);
+        let base = self.font_base as usize;
+        self.memory[base..base + self.font.len()].copy_from_slice(&self.font);
+    }
+
+    pub fn load_font(&mut self, font: &[u8], base: u16) -> io::Result<()> {
+        // Replaces the hex font, for ROMs that expect it somewhere else or want their own glyphs.
+        // Glyphs are 5 bytes each, 0 first. It's put back on reset like the standard font.
+        // FX29 can point at any of the 16 glyphs, so there has to be room for all of them even if the font is shorter.
+        // That whole space has to stay clear of the big font and the program
+        let end = base as usize + font.len().max(FONT_SET.len());
+        if end > self.memory.len() {
+            return Err(io::Error::new(io::ErrorKind::InvalidData,
+                format!("font at 0x{:03X} needs {} bytes and runs past the end of memory", base, end - base as usize)));
+        };
+        if end > self.program_start as usize {
+            return Err(io::Error::new(io::ErrorKind::InvalidData,
+                format!("font at 0x{:03X} needs {} bytes and runs into the program at 0x{:03X}", base, end - base as usize, self.program_start)));
+        };
+        let big_font = BIG_FONT_BASE as usize..BIG_FONT_BASE as usize + BIG_FONT_SET.len();
+        if (base as usize) < big_font.end && big_font.start < end {
+            return Err(io::Error::new(io::ErrorKind::InvalidData,
+                format!("font at 0x{:03X} overlaps the SCHIP big font at 0x{:03X}-0x{:03X}", base, big_font.start, big_font.end - 1)));
+        };
+        self.font = font.to_vec();
+        self.font_base = base;
+        self.load_font_set();
+        Ok(())
+    }
+
+    pub fn font_base(&self) -> u16 {
+        self.font_base
+    }
+
+    pub fn reset(&mut self) {
+        // Restart the loaded ROM without reloading it. Memory is left alone apart from the font,
+        // so anything the ROM wrote over its own code stays written
+        self.registers = Registers::new();
+        self.registers.PC = self.program_start;
+        self.halted = false;
+        self.stack.clear();
+        let was_beeping = self.is_beeping();
+        self.timers = Timers::new();
+        self.notify_sound(was_beeping);
+        self.keys = [false; 16];
+        self.pending_key = None;
+        self.display.hires = false;
+        self.Display();
+        self.draw_collided = false;
+        for level in self.intensity.iter_mut() {
+            *level = 0;
+        };
+        self.load_font_set();
+    }
+
+    pub fn initialize(&mut self) {
+        // Full wipe, including the ROM
+        self.registers = Registers::new();
+        self.halted = false;
+        self.rom_len = 0;
+
+        for byte in self.memory.iter_mut() {
+            *byte = 0;
+        };
+        self.stack.clear();
+        let was_beeping = self.is_beeping();
+        self.timers = Timers::new();
+        self.notify_sound(was_beeping);
+        // A replay belongs to the ROM it was recorded on
+        self.playback.clear();
+        self.display = Framebuffer::new();
+        self.display_dirty.set(true);
+        self.draw_collided = false;
+        for level in self.intensity.iter_mut() {
+            *level = 0;
+        };
+        self.keys = [false; 16];
+        self.pending_key = None;
+        self.rpl_flags = [0u8; 8];
+        self.load_font_set();
+    }
+
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            registers: self.registers.clone(),
+            memory: self.memory.to_vec(),
+            stack: self.stack.clone(),
+            timers: Timers { delay: self.timers.delay, sound: self.timers.sound },
+            display: self.display.pixels.iter().flat_map(|row| row.iter().cloned()).collect(),
+            hires: self.display.hires,
+            keys: self.keys,
+            rpl_flags: self.rpl_flags,
+        }
+    }
+
+    pub fn restore(&mut self, snapshot: Snapshot) -> io::Result<()> {
+        // A snapshot from a different machine can't be put back, and nothing is touched if it doesn't fit
+        if snapshot.memory.len() != self.memory.len()
+            || snapshot.display.len() != DISPLAY_WIDTH * DISPLAY_HEIGHT
+            || snapshot.stack.len() > STACK_DEPTH
+        {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "snapshot doesn't match this machine"));
+        };
+
+        self.registers = snapshot.registers;
+        self.halted = false;
+        self.memory.copy_from_slice(&snapshot.memory);
+        self.stack = snapshot.stack;
+        let was_beeping = self.is_beeping();
+        self.timers = snapshot.timers;
+        self.notify_sound(was_beeping);
+        for (row, pixels) in self.display.pixels.iter_mut().zip(snapshot.display.chunks(DISPLAY_WIDTH)) {
+            row.copy_from_slice(pixels);
+        };
+        self.display.hires = snapshot.hires;
+        self.display_dirty.set(true);
+        self.keys = snapshot.keys;
+        self.pending_key = None;
+        self.rpl_flags = snapshot.rpl_flags;
+        Ok(())
+    }
+
+    #[cfg(feature = "serialize")]
+    pub fn save_state(&self) -> Vec<u8> {
+        // Serializing plain in-memory data can't fail
+        bincode::serialize(&self.snapshot()).expect("save state should always serialize")
+    }
+
+    #[cfg(feature = "serialize")]
+    pub fn load_state(&mut self, bytes: &[u8]) -> Result<(), bincode::Error> {
+        let state: Snapshot = bincode::deserialize(bytes)?;
+        // restore checks the state fits before touching the CPU
+        self.restore(state).map_err(|_| Box::new(bincode::ErrorKind::Custom(String::from("save state doesn't match this machine"))))
+    }
+
+    #[cfg(feature = "serialize")]
+    pub fn to_json(&self) -> String {
+        // Runs of non-zero bytes, so the font and the ROM show up without thousands of zeros around them
+        let mut memory: Vec<MemoryRange> = Vec::new();
+        let mut last = None;
+        for (address, &byte) in self.memory.iter().enumerate() {
+            if byte == 0 {
+                continue;
+            };
+            match memory.last_mut() {
+                Some(range) if last.map(|a| a + 1) == Some(address) => range.bytes.push(byte),
+                _ => memory.push(MemoryRange { start: address as u16, bytes: vec![byte] }),
+            };
+            last = Some(address);
+        };
+
+        let (width, height) = self.resolution();
+
+        let state = DebugState {
+            registers: self.registers.clone(),
+            stack: self.stack.clone(),
+            timers: Timers { delay: self.timers.delay, sound: self.timers.sound },
+            memory,
+            width,
+            height,
+            display: self.display.hex_rows(),
+        };
+        // Same as save_state, plain in-memory data can't fail to serialize
+        serde_json::to_string(&state).expect("debug state should always serialize")
+    }
+
+    pub fn set_key(&mut self, key: u8, pressed: bool) {
+        // Keys outside of 0-F don't exist on the keypad and are ignored
+        if let Some(k) = self.keys.get_mut(key as usize) {
+            *k = pressed;
+            if let Some((start, log)) = self.recording.as_mut() {
+                log.events.push((self.stats.cycles.saturating_sub(*start), key, pressed));
+            };
+        };
+    }
+
+    pub fn start_recording(&mut self) {
+        // Key changes are timed by executed cycles rather than wall-clock time, so a replay doesn't depend on speed
+        self.recording = Some((self.stats.cycles, InputLog::default()));
+    }
+
+    pub fn stop_recording(&mut self) -> InputLog {
+        self.recording.take().map(|(_, log)| log).unwrap_or_default()
+    }
+
+    pub fn play_recording(&mut self, log: &InputLog) {
+        // Each key change is applied right before the cycle it was recorded at, starting from the next step
+        let start = self.stats.cycles;
+        self.playback = log.events.iter().map(|&(cycle, key, pressed)| (start + cycle, key, pressed)).collect();
+    }
+
+    fn replay_keys(&mut self) {
+        while let Some(&(cycle, key, pressed)) = self.playback.front() {
+            if cycle > self.stats.cycles {
+                break;
+            };
+            self.playback.pop_front();
+            self.set_key(key, pressed);
+        };
+    }
+
+    pub fn cycle(&mut self) -> Result<(), ExecError> {
+        self.step().map(|_| ())
+    }
+
+    pub fn step(&mut self) -> Result<StepInfo, ExecError> {
+        self.replay_keys();
+        let pc = self.registers.PC;
+        self.check_alignment(pc)?;
+        let opcode = self.fetch_instruction()?;
+        let instruction = self.decode(opcode, pc)?;
+
+        // A skipped instruction still moves PC past it, but isn't traced or counted
+        if let Some(hook) = self.pre_exec.as_mut() {
+            if !hook(&instruction, &self.registers) {
+                // F000's operand word isn't an instruction, so a skipped F000 NNNN still steps over it
+                if let Instruction::LoadLongI { .. } = instruction {
+                    self.registers.PC = self.pc_target(self.registers.PC as usize + 2)?;
+                };
+                return Ok(StepInfo { pc, opcode, instruction, display_changed: false, executed: false });
+            };
+        };
+        self.trace_instruction(pc, opcode, &instruction);
+
+        // The dirty flag belongs to the frontend, so borrow it to see what this instruction did and then put it back
+        let was_dirty = self.display_dirty.replace(false);
+        let result = self.execute(instruction.clone());
+        let display_changed = self.display_dirty.get();
+        self.display_dirty.set(was_dirty || display_changed);
+        result?;
+
+        self.stats.cycles += 1;
+        *self.stats.categories.entry(instruction.category()).or_insert(0) += 1;
+
+        Ok(StepInfo { pc, opcode, instruction, display_changed, executed: true })
+    }
+
+    pub fn debug_cycle(&mut self) -> Result<(), ExecError> {
+        let step = self.step()?;
+        println!("opcode: {:X}", step.opcode);
+        println!("instruction: {}\n", step.instruction);
+        Ok(())
+    }
+
+    pub fn enable_trace(&mut self, path: &Path) -> io::Result<()> {
+        // Every instruction from here on is logged as PC, raw opcode and mnemonic, one per line
+        // Lines are buffered, so call flush_trace (or drop the CPU) before reading the file
+        self.trace = Some(BufWriter::new(fs::File::create(path)?));
+        Ok(())
+    }
+
+    pub fn disable_trace(&mut self) -> io::Result<()> {
+        match self.trace.take() {
+            Some(mut trace) => trace.flush(),
+            None => Ok(()),
+        }
+    }
+
+    pub fn flush_trace(&mut self) -> io::Result<()> {
+        match self.trace.as_mut() {
+            Some(trace) => trace.flush(),
+            None => Ok(()),
+        }
+    }
+
+    fn trace_instruction(&mut self, pc: u16, opcode: u16, instruction: &Instruction) {
+        if let Some(trace) = self.trace.as_mut() {
+            if let Err(e) = writeln!(trace, "{:03X}: {:04X} {}", pc, opcode, instruction) {
+                // Don't keep failing on every cycle, a broken trace just stops
+                eprintln!("Trace stopped: {}", e);
+                self.trace = None;
+            };
+        };
+    }
+
+    fn store_byte(&mut self, address: usize, value: u8) {
+        // Every instruction that stores to memory goes through here so watch_code_writes sees it.
+        // The program runs from program_start up, and PC is already past the current instruction,
+        // so anything in between is code that has run or is running now
+        if self.quirks.watch_code_writes && address >= self.program_start as usize && address < self.registers.PC as usize {
+            self.stats.code_writes += 1;
+            if let Some(trace) = self.trace.as_mut() {
+                if let Err(e) = writeln!(trace, "     code write to {:03X}", address) {
+                    eprintln!("Trace stopped: {}", e);
+                    self.trace = None;
+                };
+            };
+        };
+        self.memory[address] = value;
+    }
+
+    fn check_alignment(&self, pc: u16) -> Result<(), ExecError> {
+        // Instructions are 2 bytes, so an odd PC nearly always means a bad jump or skip.
+        // Real interpreters execute from odd addresses anyway, so this is opt-in
+        if self.quirks.require_alignment && pc & 1 != 0 {
+            return Err(ExecError::Misaligned { pc });
+        };
+        Ok(())
+    }
+
+    fn decode(&self, opcode: u16, pc: u16) -> Result<Instruction, DecodeError> {
+        // parse_opcode knows every instruction, but the SCHIP scrolls, exit, resolution switches, big font and RPL flags only exist with the schip quirk on
+        // F000 is the one instruction longer than an opcode, so it's decoded here where the next word can be read
+        if opcode == 0xF000 {
+            return if self.quirks.xochip {
+                Ok(Instruction::LoadLongI { value: self.read_word(pc.wrapping_add(2)) })
+            } else {
+                Err(DecodeError { kind: DecodeErrorKind::UnsupportedInstruction, opcode, pc })
+            };
+        };
+        let instruction = CPU::parse_opcode(opcode).map_err(|e| DecodeError { pc, ..e })?;
+        match instruction {
+            Instruction::SCRD { .. } | Instruction::SCRR | Instruction::SCRL | Instruction::Exit | Instruction::LOW | Instruction::HIGH
+            | Instruction::DUMPF { .. } | Instruction::LOADF { .. } | Instruction::BigSprite { .. } if !self.quirks.schip => {
+                Err(DecodeError { kind: DecodeErrorKind::UnsupportedInstruction, opcode, pc })
+            },
+            _ => Ok(instruction),
+        }
+    }
+
+    pub fn run_cycles(&mut self, n: usize) -> Result<(), ExecError> {
+        // Run n cycles without any interaction, stopping at the first opcode that can't be decoded
+        // or when the program halts
+        for _ in 0..n {
+            if self.halted {
+                break;
+            };
+            self.cycle()?;
+        };
+        Ok(())
+    }
+
+    pub fn cycle_cost(instruction: &Instruction) -> u32 {
+        // How much of a frame's cycle budget an instruction uses. Most instructions cost 1, so cycles_per_second
+        // is close to instructions per second. DUMP and LOAD loop over registers on real interpreters and
+        // cost 1 per register moved. DRAW's much longer run time on the VIP is covered by the display_wait
+        // quirk instead of a cost here
+        match instruction {
+            Instruction::DUMP { register } | Instruction::LOAD { register } => Target_Register::register_to_u8(register) as u32 + 1,
+            _ => 1,
+        }
+    }
+
+    pub fn run_frame(&mut self, cycles_per_second: u32) -> Result<(), ExecError> {
+        // One 60Hz frame: spend this frame's share of cycles_per_second on instructions, then tick the timers once,
+        // so timer speed doesn't depend on cycle rate. Stops early if the program halts
+        self.frame(cycles_per_second, u64::MAX).map(|_| ())
+    }
+
+    fn frame(&mut self, cycles_per_second: u32, limit: u64) -> Result<u64, ExecError> {
+        // run_frame, but stopping after limit instructions. Returns how many instructions ran
+        self.owed += cycles_per_second as u64;
+        let budget = self.owed / TIMER_HZ as u64;
+        self.owed %= TIMER_HZ as u64;
+
+        let mut spent = 0;
+        let mut steps = 0;
+        while spent < budget && steps < limit && !self.halted {
+            let step = self.step()?;
+            steps += 1;
+            spent += CPU::cycle_cost(&step.instruction) as u64;
+            if let Instruction::DRAW { .. } = step.instruction {
+                if self.quirks.display_wait && step.executed {
+                    // Nothing else runs until the display interrupt, which is the start of the next frame.
+                    // A DRAW the pre_exec hook skipped never touched the display, so it doesn't wait
+                    break;
+                };
+            };
+        };
+
+        self.tick_timers();
+        Ok(steps)
+    }
+
+    pub fn set_max_cycles(&mut self, max_cycles: Option<u64>) {
+        // Caps how many instructions each call to run executes, for ROMs that can't be trusted to halt.
+        // The count is exact, so the same ROM and seed always stop in the same place
+        self.max_cycles = max_cycles;
+    }
+
+    pub fn run(&mut self, cycles_per_second: u32) -> Result<RunStatus, ExecError> {
+        // Runs in real time until the program halts, the max_cycles budget runs out, or an error stops execution
+        let frame = Duration::from_secs(1) / TIMER_HZ;
+        let mut next_frame = Instant::now() + frame;
+        let mut remaining = self.max_cycles.unwrap_or(u64::MAX);
+
+        loop {
+            remaining -= self.frame(cycles_per_second, remaining)?;
+            if self.halted {
+                return Ok(RunStatus::Halted);
+            };
+            if remaining == 0 {
+                return Ok(RunStatus::BudgetExhausted);
+            };
+
+            let now = Instant::now();
+            if next_frame > now {
+                thread::sleep(next_frame - now);
+                next_frame += frame;
+            } else {
+                // Running behind, start the next frame from now rather than rushing to catch up
+                next_frame = now + frame;
+            };
+        };
+    }
+
+    pub fn registers(&self) -> &Registers {
+        // Read-only view for inspection, clone it to keep a copy to compare against later
+        &self.registers
+    }
+
+    pub fn stats(&self) -> &Stats {
+        &self.stats
+    }
+
+    pub fn reset_stats(&mut self) {
+        self.stats = Stats::default();
+    }
+
+    pub fn is_halted(&self) -> bool {
+        self.halted
+    }
+
+    pub fn framebuffer(&self) -> &Framebuffer {
+        // Reading the framebuffer counts as the frontend catching up with any changes
+        self.display_dirty.set(false);
+        &self.display
+    }
+
+    pub fn last_draw_collided(&self) -> bool {
+        // Whether the most recent DRAW turned off a lit pixel, unaffected by later writes to VF
+        self.draw_collided
+    }
+
+    pub fn display_dirty(&self) -> bool {
+        // True if the display changed since framebuffer was last called, so frontends can skip redrawing
+        self.display_dirty.get()
+    }
+
+    pub fn set_ghosting(&mut self, on: bool) {
+        // CRT style fading for frontends: lit pixels are at full intensity and pixels that
+        // go out fade over a few frames instead of vanishing, which also hides sprite flicker
+        if on {
+            self.intensity = self.display.pixels.iter().flat_map(|row| row.iter().map(|&lit| if lit { 255 } else { 0 })).collect();
+        } else {
+            self.intensity = Vec::new();
+        };
+    }
+
+    pub fn pixel_intensity(&self) -> &[u8] {
+        // Row by row, DISPLAY_WIDTH pixels per row like the framebuffer. Empty with ghosting off
+        &self.intensity
+    }
+
+    fn fade_pixels(&mut self) {
+        // Once per frame, pixels still lit stay at full intensity and the rest dim
+        for (level, &lit) in self.intensity.iter_mut().zip(self.display.pixels.iter().flat_map(|row| row.iter())) {
+            *level = if lit { 255 } else { level.saturating_sub(GHOST_FADE) };
+        };
+    }
+
+    pub fn resolution(&self) -> (usize, usize) {
+        // Width and height of the active display mode
+        self.display.resolution()
+    }
+
+    pub fn tick_timers(&mut self) {
+        // Called TIMER_HZ times per second by run_frame and the debugger
+        // Doubles as the frame tick for ghosting
+        self.decrement_timers();
+        self.fade_pixels();
+    }
+
+    pub fn decrement_timers(&mut self) {
+        // One 60Hz step of both timers, stopping at 0. For frontends that keep their own frame clock
+        let was_beeping = self.is_beeping();
+        self.timers.delay = self.timers.delay.saturating_sub(1);
+        self.timers.sound = self.timers.sound.saturating_sub(1);
+        self.notify_sound(was_beeping);
+    }
+
+    pub fn delay_timer(&self) -> u8 {
+        self.timers.delay
+    }
+
+    pub fn sound_timer(&self) -> u8 {
+        self.timers.sound
+    }
+
+    pub fn set_delay_timer(&mut self, value: u8) {
+        self.timers.delay = value;
+    }
+
+    pub fn set_sound_timer(&mut self, value: u8) {
+        // Goes through the sound callback the same way FX18 does
+        let was_beeping = self.is_beeping();
+        self.timers.sound = value;
+        self.notify_sound(was_beeping);
+    }
+
+    pub fn is_beeping(&self) -> bool {
+        // The buzzer sounds for as long as the sound timer is above 0
+        self.timers.sound > 0
+    }
+
+    pub fn set_sound_callback<F: FnMut(bool) + Send + 'static>(&mut self, callback: F) {
+        // Lets a frontend start and stop a tone without the core knowing about any audio backend
+        self.sound_callback = Some(Box::new(callback));
+    }
+
+    pub fn set_pre_exec<F: FnMut(&Instruction, &Registers) -> bool + Send + 'static>(&mut self, hook: F) {
+        // For breakpoints, cheats and logging. The hook gets each decoded instruction and the registers
+        // before it runs, with PC already past it, and returning false skips the instruction
+        self.pre_exec = Some(Box::new(hook));
+    }
+
+    pub fn clear_pre_exec(&mut self) {
+        self.pre_exec = None;
+    }
+
+    fn notify_sound(&mut self, was_beeping: bool) {
+        let beeping = self.is_beeping();
+        if beeping != was_beeping {
+            if let Some(callback) = self.sound_callback.as_mut() {
+                callback(beeping);
+            };
+        };
+    }
+
+    fn read_register(&self, register: &Target_Register) -> u8 {
+        // Only covers V0-VF, I and PC are 16 bit and handled explicitly by the opcodes that use them
+        match register {
+            Target_Register::I | Target_Register::PC => 0,
+            _ => self.registers.V[Target_Register::register_to_u8(register) as usize],
+        }
+    }
+
+    fn memory_index(&self, offset: usize) -> Result<usize, ExecError> {
+        // Address offset bytes past I, for every instruction that reads or writes memory through I.
+        // Past the end it wraps around to the start of memory, or with strict_index on it's an error
+        let address = self.registers.I as usize + offset;
+        if address < self.memory.len() {
+            Ok(address)
+        } else if self.quirks.strict_index {
+            Err(ExecError::IndexOutOfRange { pc: self.registers.PC.wrapping_sub(2), address })
+        } else {
+            Ok(address % self.memory.len())
+        }
+    }
+
+    fn read_word(&self, addr: u16) -> u16 {
+        // Big endian word at addr, wrapping around the end of memory. For looking ahead, fetch itself never wraps
+        let size = self.memory.len();
+        let addr = addr as usize % size;
+        (self.memory[addr] as u16) << 8 | self.memory[(addr + 1) % size] as u16
+    }
+
+    fn skip_width(&self) -> u16 {
+        // How far a skip moves PC: past the next instruction, which with xochip on can be a four byte F000 NNNN
+        if self.quirks.xochip && self.read_word(self.registers.PC) == 0xF000 { 4 } else { 2 }
+    }
+
+    fn skip_next(&mut self) -> Result<(), ExecError> {
+        // Step PC over the next instruction for the conditional skips
+        self.registers.PC = self.pc_target(self.registers.PC as usize + self.skip_width() as usize)?;
+        Ok(())
+    }
+
+    fn rewind(&mut self) {
+        // Step PC back onto the instruction just fetched so it runs again.
+        // Fetch only moves PC forward by 2 from an address inside memory, so this can't go below 0
+        self.registers.PC -= 2;
+    }
+
+    fn pc_target(&self, address: usize) -> Result<u16, ExecError> {
+        // Every jump and skip that adds to PC goes through here, PC has to stay inside memory rather than wrap
+        if address < self.memory.len() {
+            Ok(address as u16)
+        } else {
+            Err(ExecError::AddressOverflow { pc: self.registers.PC.wrapping_sub(2), address })
+        }
+    }
+
+    fn i_plus(&self, value: u16) -> Result<u16, ExecError> {
+        // I is 16 bit and doesn't wrap. Pointing past the end of memory is fine until something reads through it
+        self.registers.I.checked_add(value)
+            .ok_or(ExecError::AddressOverflow { pc: self.registers.PC.wrapping_sub(2), address: self.registers.I as usize + value as usize })
+    }
+
+    fn write_register(&mut self, register: &Target_Register, value: u8) {
+        // Only covers V0-VF, I and PC are 16 bit and handled explicitly by the opcodes that use them
+        match register {
+            Target_Register::I | Target_Register::PC => (),
+            _ => self.registers.V[Target_Register::register_to_u8(register) as usize] = value,
+        };
+    }
+
+    pub fn screenshot_ppm(&self, scale: usize) -> Vec<u8> {
+        // Binary PPM of the active display area, each pixel drawn as a scale x scale square of white or black
+        let scale = scale.max(1);
+        let (width, height) = self.resolution();
+        let mut out = format!("P6\n{} {}\n255\n", width * scale, height * scale).into_bytes();
+        for row in self.display.rows() {
+            for _ in 0..scale {
+                for &pixel in row {
+                    let shade = if pixel { 255 } else { 0 };
+                    for _ in 0..scale {
+                        out.extend_from_slice(&[shade, shade, shade]);
+                    };
+                };
+            };
+        };
+        out
+    }
+
+    #[cfg(feature = "png")]
+    pub fn screenshot_png(&self, scale: usize) -> Vec<u8> {
+        // Same picture as screenshot_ppm, encoded as a greyscale PNG
+        let scale = scale.max(1);
+        let (width, height) = self.resolution();
+        let image = image::GrayImage::from_fn((width * scale) as u32, (height * scale) as u32, |x, y| {
+            let lit = self.display.get(x as usize / scale, y as usize / scale);
+            image::Luma([if lit { 255 } else { 0 }])
+        });
+        let mut out = Vec::new();
+        // Encoding into memory has no I/O to fail on
+        image::DynamicImage::ImageLuma8(image).write_to(&mut out, image::ImageOutputFormat::Png)
+            .expect("PNG should always encode into memory");
+        out
+    }
+
+    pub fn render_to_terminal(&self) {
+        print!("{}", self.display.to_terminal());
+    }
+
+    pub fn print_registers_state(&self) {
+        println!("Current CPU registers
+        {:?}", self.registers);
+    }
+
+    pub fn print_memory(&self, address: usize, length: usize) {
+        // Hex dump 16 bytes to a line, each line starting with the address of its first byte
+        if address >= self.memory.len() || length > self.memory.len() - address {
+            eprintln!("Memory range 0x{:03X} + {} is outside of 0x000-0x{:03X}", address, length, self.memory.len() - 1);
+            return;
+        };
+
+        for (line, bytes) in self.memory[address..address + length].chunks(16).enumerate() {
+            let hex: Vec<String> = bytes.iter().map(|b| format!("{:02X}", b)).collect();
+            println!("0x{:03X}: {}", address + line * 16, hex.join(" "));
+        };
+    }
+
+    pub fn parse_opcode(opcode: u16) -> Result<Instruction, DecodeError> {
+        // Decipher opcode and prepare registers accordingly
+        // Doesn't know where the opcode came from, so errors have pc 0 for the caller to fill in
+        // Opcodes that don't map to an instruction return an error instead of guessing. In the families that
+        // pick their instruction with the low bits (5XY0, 8XY_, 9XY0, EX__, FX__) the error names those bits
+        let mut instruction = None;
+        let sub_opcode_error = |sub_opcode| DecodeError { kind: DecodeErrorKind::UnknownSubOpcode { sub_opcode }, opcode, pc: 0 };
+
+        match opcode & 0xF000 {
+            0x0000 => {
+                // 0NNN calls RCA 1802 machine code on the original hardware, which isn't emulated
+                // Anything in this range other than 00E0, 00EE, the SCHIP display and exit instructions (and 0000, treated as NOP) is unsupported
+                match opcode {
+                    0x0000 => instruction = Some(Instruction::NOP),
+                    0x00E0 => instruction = Some(Instruction::Display),
+                    0x00EE => instruction = Some(Instruction::Return),
+                    0x00FE => instruction = Some(Instruction::LOW),
+                    0x00FF => instruction = Some(Instruction::HIGH),
+                    0x00FB => instruction = Some(Instruction::SCRR),
+                    0x00FC => instruction = Some(Instruction::SCRL),
+                    0x00FD => instruction = Some(Instruction::Exit),
+                    x if x & 0xFFF0 == 0x00C0 => instruction = Some(Instruction::SCRD { lines: (x & 0x000F) as u8 }),
+                    _ => return Err(DecodeError { kind: DecodeErrorKind::UnsupportedInstruction, opcode, pc: 0 }),
+                }
+            },
+            0x1000 => instruction = Some(Instruction::JUMP { address: opcode & 0x0FFF }),
+            0x2000 => instruction = Some(Instruction::Call { address: opcode & 0x0FFF }),
+            0x3000 => instruction = Some(Instruction::SKEQ { register: Target_Register::u8_to_register(((opcode >> 8) & 0x0F) as u8), value: (opcode & 0x00FF) as u8 }),
+            0x4000 => instruction = Some(Instruction::SKNEQ { register: Target_Register::u8_to_register(((opcode >> 8) & 0x0F) as u8), value: (opcode & 0x00FF) as u8}),
+            0x5000 if opcode & 0x000F == 0 => instruction = Some(Instruction::SKREQ { register1: Target_Register::u8_to_register(((opcode >> 8) & 0x0F) as u8), register2: Target_Register::u8_to_register(((opcode >> 4) & 0x0F) as u8)}),
+            0x6000 => instruction = Some(Instruction::SET { register: Target_Register::u8_to_register(((opcode >> 8) & 0x0F) as u8), value: (opcode & 0x00FF) as u8}),
+            0x7000 => instruction = Some(Instruction::ADD { register: Target_Register::u8_to_register(((opcode >> 8) & 0x0F) as u8), value: (opcode & 0x00FF) as u8}),
+            0x8000 => {
+                match opcode & 0xF00F {
+                    0x8000 => instruction = Some(Instruction::COPYR { register1: Target_Register::u8_to_register(((opcode >> 8) & 0x0F) as u8), register2: Target_Register::u8_to_register(((opcode >> 4) & 0x0F) as u8) }),
+                    0x8001 => instruction = Some(Instruction::OR { register1: Target_Register::u8_to_register(((opcode >> 8) & 0x0F) as u8), register2: Target_Register::u8_to_register(((opcode >> 4) & 0x0F) as u8) }),
+                    0x8002 => instruction = Some(Instruction::AND { register1: Target_Register::u8_to_register(((opcode >> 8) & 0x0F) as u8), register2: Target_Register::u8_to_register(((opcode >> 4) & 0x0F) as u8) }),
+                    0x8003 => instruction = Some(Instruction::XOR { register1: Target_Register::u8_to_register(((opcode >> 8) & 0x0F) as u8), register2: Target_Register::u8_to_register(((opcode >> 4) & 0x0F) as u8) }),
+                    0x8004 => instruction = Some(Instruction::ADDR { register1: Target_Register::u8_to_register(((opcode >> 8) & 0x0F) as u8), register2: Target_Register::u8_to_register(((opcode >> 4) & 0x0F) as u8) }),
+                    0x8005 => instruction = Some(Instruction::SUBX { register1: Target_Register::u8_to_register(((opcode >> 8) & 0x0F) as u8), register2: Target_Register::u8_to_register(((opcode >> 4) & 0x0F) as u8) }),
+                    0x8006 => instruction = Some(Instruction::SHFTR { register1: Target_Register::u8_to_register(((opcode >> 8) & 0x0F) as u8), register2: Target_Register::u8_to_register(((opcode >> 4) & 0x0F) as u8) }),
+                    0x8007 => instruction = Some(Instruction::SUBY { register1: Target_Register::u8_to_register(((opcode >> 8) & 0x0F) as u8), register2: Target_Register::u8_to_register(((opcode >> 4) & 0x0F) as u8) }),
+                    0x800E => instruction = Some(Instruction::SHFTL { register1: Target_Register::u8_to_register(((opcode >> 8) & 0x0F) as u8), register2: Target_Register::u8_to_register(((opcode >> 4) & 0x0F) as u8) }),
+                    _ => return Err(sub_opcode_error((opcode & 0x000F) as u8)),
+                }
+            },
+            0x9000 if opcode & 0x000F == 0 => instruction = Some(Instruction::SKRNEQ { register1: Target_Register::u8_to_register(((opcode >> 8) & 0x0F) as u8), register2: Target_Register::u8_to_register(((opcode >> 4) & 0x0F) as u8)}),
+            0x5000 | 0x9000 => return Err(sub_opcode_error((opcode & 0x000F) as u8)),
+            0xA000 => instruction = Some(Instruction::SETI { value: opcode & 0x0FFF }),
+            0xB000 => instruction = Some(Instruction::JMP0 { address: opcode & 0x0FFF}),
+            0xC000 => instruction = Some(Instruction::RAND { register: Target_Register::u8_to_register(((opcode >> 8) & 0x0F) as u8), value: (opcode & 0x00FF) as u8}),
+            0xD000 => instruction = Some(Instruction::DRAW { register1: Target_Register::u8_to_register(((opcode >> 8) & 0x0F) as u8), register2: Target_Register::u8_to_register(((opcode >> 4) & 0x0F) as u8), height: (opcode & 0x000F) as u8}),
+            0xE000 => {
+                match opcode & 0xF0FF {
+                    0xE09E => instruction = Some(Instruction::SKKEQ { register: Target_Register::u8_to_register(((opcode >> 8) & 0x0F) as u8) }),
+                    0xE0A1 => instruction = Some(Instruction::SKKNEQ { register: Target_Register::u8_to_register(((opcode >> 8) & 0x0F) as u8) }),
+                    _ => return Err(sub_opcode_error((opcode & 0x00FF) as u8)),
+                }
+            },
+            0xF000 => {
+                match opcode & 0xF0FF {
+                    0xF007 => instruction = Some(Instruction::SETXD { register: Target_Register::u8_to_register(((opcode >> 8) & 0x0F) as u8) }),
+                    0xF00A => instruction = Some(Instruction::STORE { register: Target_Register::u8_to_register(((opcode >> 8) & 0x0F) as u8) }),
+                    0xF015 => instruction = Some(Instruction::SETD { register: Target_Register::u8_to_register(((opcode >> 8) & 0x0F) as u8) }),
+                    0xF018 => instruction = Some(Instruction::SETS { register: Target_Register::u8_to_register(((opcode >> 8) & 0x0F) as u8) }),
+                    0xF01E => instruction = Some(Instruction::ADDI { register: Target_Register::u8_to_register(((opcode >> 8) & 0x0F) as u8) }),
+                    0xF029 => instruction = Some(Instruction::SPRITE { register: Target_Register::u8_to_register(((opcode >> 8) & 0x0F) as u8) }),
+                    0xF033 => instruction = Some(Instruction::BCD { register: Target_Register::u8_to_register(((opcode >> 8) & 0x0F) as u8) }),
+                    0xF055 => instruction = Some(Instruction::DUMP { register: Target_Register::u8_to_register(((opcode >> 8) & 0x0F) as u8) }),
+                    0xF065 => instruction = Some(Instruction::LOAD { register: Target_Register::u8_to_register(((opcode >> 8) & 0x0F) as u8) }),
+                    0xF075 => instruction = Some(Instruction::DUMPF { register: Target_Register::u8_to_register(((opcode >> 8) & 0x0F) as u8) }),
+                    0xF085 => instruction = Some(Instruction::LOADF { register: Target_Register::u8_to_register(((opcode >> 8) & 0x0F) as u8) }),
+                    0xF030 => instruction = Some(Instruction::BigSprite { register: Target_Register::u8_to_register(((opcode >> 8) & 0x0F) as u8) }),
+                    _ => return Err(sub_opcode_error((opcode & 0x00FF) as u8)),
+                }
+            },
+            _ => (),
+        };
+
+        match instruction {
+            Some(x) => Ok(x),
+            None => Err(DecodeError { kind: DecodeErrorKind::UnknownOpcode, opcode, pc: 0 }),
+        }
+    }
+
+    fn execute(&mut self, instruction: Instruction) -> Result<(), ExecError> {
+        // Every Instruction has an arm, opcodes that aren't instructions already failed in decode as ExecError::Decode
+        // Memory accesses wrap around unless strict_index is on. Arithmetic never panics, even in debug builds:
+        // V registers wrap at 8 bits with VF reporting carries where the instruction calls for it,
+        // while I going past 0xFFFF or PC leaving memory is an AddressOverflow
+        match instruction {
+            Instruction::NOP => (),
+            Instruction::Display => self.Display(),
+            Instruction::Return => self.Return()?,
+            Instruction::LOW => self.LOW(),
+            Instruction::HIGH => self.HIGH(),
+            Instruction::SCRD { lines: n } => self.SCRD(n),
+            Instruction::SCRR => self.SCRR(),
+            Instruction::SCRL => self.SCRL(),
+            Instruction::Exit => self.Exit(),
+            Instruction::JUMP { address: a } => self.JUMP(a),
+            Instruction::Call { address: a } => self.Call(a)?,
+            Instruction::SKEQ { register: r, value: v } => self.SKEQ(r, v)?,
+            Instruction::SKNEQ { register: r, value: v } => self.SKNEQ(r, v)?,
+            Instruction::SKREQ { register1: r1, register2: r2 } => self.SKREQ(r1, r2)?,
+            Instruction::SET { register: r, value: v } => self.SET(r, v),
+            Instruction::ADD { register: r, value: v } => self.ADD(r, v)?,
+            Instruction::COPYR { register1: r1, register2: r2 } => self.COPYR(r1, r2),
+            Instruction::OR { register1: r1, register2: r2 } => self.OR(r1, r2),
+            Instruction::AND { register1: r1, register2: r2 } => self.AND(r1, r2),
+            Instruction::XOR { register1: r1, register2: r2 } => self.XOR(r1, r2),
+            Instruction::ADDR { register1: r1, register2: r2 } => self.ADDR(r1, r2),
+            Instruction::SUBX { register1: r1, register2: r2 } => self.SUBX(r1, r2),
+            Instruction::SHFTR { register1: r1, register2: r2 } => self.SHFTR(r1, r2),
+            Instruction::SUBY { register1: r1, register2: r2 } => self.SUBY(r1, r2),
+            Instruction::SHFTL { register1: r1, register2: r2 } => self.SHFTL(r1, r2),
+            Instruction::SKRNEQ { register1: r1, register2: r2 } => self.SKRNEQ(r1, r2)?,
+            Instruction::SETI { value: v } => self.SETI(v),
+            Instruction::JMP0 { address: a } => self.JMP0(a)?,
+            Instruction::RAND { register: r, value: v } => self.RAND(r, v),
+            Instruction::DRAW { register1: r1, register2: r2, height: h } => self.DRAW(r1, r2, h)?,
+            Instruction::SKKEQ { register: r } => self.SKKEQ(r)?,
+            Instruction::SKKNEQ { register: r } => self.SKKNEQ(r)?,
+            Instruction::SETXD { register: r } => self.SETXD(r),
+            Instruction::STORE { register: r } => self.STORE(r),
+            Instruction::SETD { register: r } => self.SETD(r),
+            Instruction::SETS { register: r } => self.SETS(r),
+            Instruction::ADDI { register: r } => self.ADDI(r)?,
+            Instruction::SPRITE { register: r } => self.SPRITE(r),
+            Instruction::BCD { register: r } => self.BCD(r)?,
+            Instruction::DUMP { register: r } => self.DUMP(r)?,
+            Instruction::LOAD { register: r } => self.LOAD(r)?,
+            Instruction::DUMPF { register: r } => self.DUMPF(r),
+            Instruction::LOADF { register: r } => self.LOADF(r),
+            Instruction::BigSprite { register: r } => self.BigSprite(r),
+            Instruction::LoadLongI { value: v } => self.LoadLongI(v)?,
+        };
+        Ok(())
+    }
+
+    fn Display(&mut self) {
+        // Clears the screen when called
+        self.display.clear();
+        self.display_dirty.set(true);
+    }
+
+    fn LOW(&mut self) {
+        // Switch to 64x32, the screen is cleared since old pixels don't line up with the new grid
+        self.display.hires = false;
+        self.Display();
+    }
+
+    fn HIGH(&mut self) {
+        // Switch to 128x64, clearing the screen like LOW
+        self.display.hires = true;
+        self.Display();
+    }
+
+    fn SCRD(&mut self, lines: u8) {
+        // Scroll the active display area down, rows coming in at the top are blank
+        let (width, height) = self.resolution();
+        let lines = lines as usize;
+        for y in (0..height).rev() {
+            for x in 0..width {
+                let lit = y >= lines && self.display.get(x, y - lines);
+                self.display.set(x, y, lit);
+            };
+        };
+        self.display_dirty.set(true);
+    }
+
+    fn SCRR(&mut self) {
+        // Scroll the active display area right 4 columns, columns coming in on the left are blank
+        let (width, height) = self.resolution();
+        for y in 0..height {
+            for x in (0..width).rev() {
+                let lit = x >= 4 && self.display.get(x - 4, y);
+                self.display.set(x, y, lit);
+            };
+        };
+        self.display_dirty.set(true);
+    }
+
+    fn SCRL(&mut self) {
+        // Scroll the active display area left 4 columns, columns coming in on the right are blank
+        let (width, height) = self.resolution();
+        for y in 0..height {
+            for x in 0..width {
+                let lit = self.display.get(x + 4, y);
+                self.display.set(x, y, lit);
+            };
+        };
+        self.display_dirty.set(true);
+    }
+
+    fn Exit(&mut self) {
+        // PC is left on the 00FD so stepping any further keeps stopping here, same as a jump to itself
+        self.halted = true;
+        self.rewind();
+    }
+
+    fn Return(&mut self) -> Result<(), ExecError> {
+        // Returning with nothing on the stack is a ROM bug, report it rather than panicking
+        match self.stack.pop() {
+            Some(address) => {
+                self.registers.PC = address;
+                Ok(())
+            },
+            None => Err(ExecError::StackUnderflow { pc: self.registers.PC.wrapping_sub(2) }),
+        }
+    }
+    
+    fn JUMP(&mut self, address: u16) {
+        // A jump to itself can never get anywhere else, so flag the program as finished
+        if address == self.registers.PC.wrapping_sub(2) {
+            self.halted = true;
+        };
+        self.registers.PC = address;
+    }
+
+    fn Call(&mut self, address: u16) -> Result<(), ExecError> {
+        if self.stack.len() >= STACK_DEPTH {
+            return Err(ExecError::StackOverflow { pc: self.registers.PC.wrapping_sub(2) });
+        };
+
+        self.stack.push(self.registers.PC);
+        self.registers.PC = address;
+        Ok(())
+    }
+
+    fn SKEQ(&mut self, register: Target_Register, value: u8) -> Result<(), ExecError> {
+        // Skip the next instruction if Register == Value
+        // 3XNN only ever names V0-VF
+
+        if self.read_register(&register) == value {
+            self.skip_next()?;
+        };
+        Ok(())
+    }
+
+    fn SKNEQ(&mut self, register: Target_Register, value: u8) -> Result<(), ExecError> {
+        // Skip the next instruction if Register != Value
+        // 4XNN only ever names V0-VF
+
+        if self.read_register(&register) != value {
+            self.skip_next()?;
+        };
+        Ok(())
+    }
+
+    fn SKREQ(&mut self, register1: Target_Register, register2: Target_Register) -> Result<(), ExecError> {
+        // Skip next instruction if specified registers are equal
+        // 5XY0 only ever names V0-VF
+
+        if self.read_register(&register1) == self.read_register(&register2) {
+            self.skip_next()?;
+        };
+        Ok(())
+    }
+
+    fn SET(&mut self, register: Target_Register, value: u8) {
+        match register {
+            Target_Register::I => self.registers.I = value as u16,
+            Target_Register::PC => self.registers.PC = value as u16,
+            _ => self.write_register(&register, value),
+        };
+    }
+
+    fn ADD(&mut self, register: Target_Register, value: u8) -> Result<(), ExecError> {
+        // Carry flag is not taken into account with this instruction
+        
+        match register {
+            Target_Register::I => self.registers.I = self.i_plus(value as u16)?,
+            Target_Register::PC => self.registers.PC = self.pc_target(self.registers.PC as usize + value as usize)?,
+            _ => {
+                let r = self.read_register(&register);
+                self.write_register(&register, r.wrapping_add(value));
+            },
+        };
+        Ok(())
+    }
+
+    fn COPYR(&mut self, register1: Target_Register, register2: Target_Register) {
+        // Copy value from register2 to register1
+
+        let r2 = self.read_register(&register2);
+        self.write_register(&register1, r2);
+    }
+
+    fn OR(&mut self, register1: Target_Register, register2: Target_Register) {
+        // Register1 = Register1 | Register2
+        // With the vf_reset quirk enabled VF is cleared afterwards, same for AND and XOR
+        // Like the rest of the 8XYN family both registers are read before anything is written,
+        // so register1 and register2 can be the same register
+
+        let r1 = self.read_register(&register1);
+        let r2 = self.read_register(&register2);
+        self.write_register(&register1, r1 | r2);
+
+        if self.quirks.vf_reset {
+            self.registers.V[0xF] = 0;
+        };
+    }
+
+    fn AND(&mut self, register1: Target_Register, register2: Target_Register) {
+        // Register1 = Register1 & Register2
+
+        let r1 = self.read_register(&register1);
+        let r2 = self.read_register(&register2);
+        self.write_register(&register1, r1 & r2);
+
+        if self.quirks.vf_reset {
+            self.registers.V[0xF] = 0;
+        };
+    }
+
+    fn XOR(&mut self, register1: Target_Register, register2: Target_Register) {
+        // Register1 = Register1 ^ Register2
+
+        let r1 = self.read_register(&register1);
+        let r2 = self.read_register(&register2);
+        self.write_register(&register1, r1 ^ r2);
+
+        if self.quirks.vf_reset {
+            self.registers.V[0xF] = 0;
+        };
+    }
+
+    fn ADDR(&mut self, register1: Target_Register, register2: Target_Register) {
+        // Register1 += Register2 Affects the carry flag (VF is 1 on carry, 0 otherwise)
+        // The sum is written before VF so that VF holds the flag when it's the target
+
+        let r1 = self.read_register(&register1);
+        let r2 = self.read_register(&register2);
+        let (value, carry) = r1.overflowing_add(r2);
+
+        self.write_register(&register1, value);
+        self.registers.V[0xF] = if carry { 1 } else { 0 };
+    }
+
+    fn SUBX(&mut self, register1: Target_Register, register2: Target_Register) {
+        // Register1 -= Register2 Affects Borrow flag
+        // VF is set to 1 when there is no borrow and 0 when there is
+        // Both operands are read before anything is written and VF goes last, so with VF as either operand the flag wins
+
+        let r1 = self.read_register(&register1);
+        let r2 = self.read_register(&register2);
+        let (value, borrow) = r1.overflowing_sub(r2);
+
+        self.write_register(&register1, value);
+        self.registers.V[0xF] = if borrow { 0 } else { 1 };
+    }
+
+    fn SHFTR(&mut self, register1: Target_Register, register2: Target_Register) {
+        // Store LeastSignificantBit in flag register then shift register1 to the right by 1
+        // With the shift quirk enabled register2 is shifted into register1 instead
+        // The bit is captured before the shift and VF is written last so shifting VF keeps the flag
+
+        let source = if self.quirks.shift { &register2 } else { &register1 };
+        let r = self.read_register(source);
+        let flag = r & 0x01;
+
+        self.write_register(&register1, r >> 1);
+        self.registers.V[0xF] = flag;
+    }
+
+    fn SUBY(&mut self, register1: Target_Register, register2: Target_Register) {
+        // Register1 = Register2 - Register1 Affects Borrow flag
+        // VF is set to 1 when there is no borrow and 0 when there is
+        // Same ordering as SUBX, so 8FY7 and 8XF7 still end with the borrow flag in VF
+
+        let r1 = self.read_register(&register1);
+        let r2 = self.read_register(&register2);
+        let (value, borrow) = r2.overflowing_sub(r1);
+
+        self.write_register(&register1, value);
+        self.registers.V[0xF] = if borrow { 0 } else { 1 };
+    }
+
+    fn SHFTL(&mut self, register1: Target_Register, register2: Target_Register) {
+        // Store MostSignificantBit in flag register then shift register1 to the left by 1
+        // With the shift quirk enabled register2 is shifted into register1 instead, same as SHFTR
+        // Like SHFTR the bit is captured first and VF written last, so SHFTL VF leaves the flag
+
+        let source = if self.quirks.shift { &register2 } else { &register1 };
+        let r = self.read_register(source);
+        let flag = r >> 7;
+
+        self.write_register(&register1, r << 1);
+        self.registers.V[0xF] = flag;
+    }
+
+    fn SKRNEQ(&mut self, register1: Target_Register, register2: Target_Register) -> Result<(), ExecError> {
+        // Skip next instruction if register1 and register2 are not equal
+        // 9XY0 only ever names V0-VF
+
+        if self.read_register(&register1) != self.read_register(&register2) {
+            self.skip_next()?;
+        };
+        Ok(())
+    }
+
+    fn SETI(&mut self, value: u16) {
+        self.registers.I = value;
+    }
+
+    fn JMP0(&mut self, address: u16) -> Result<(), ExecError> {
+        // PC = address + V0 register
+        // With the jump quirk enabled the highest nibble of the address picks the register instead (BXNN)
+
+        let register = if self.quirks.jump {
+            Target_Register::u8_to_register(((address >> 8) & 0x0F) as u8)
+        } else {
+            Target_Register::V0
+        };
+
+        self.registers.PC = self.pc_target(address as usize + self.read_register(&register) as usize)?;
+        Ok(())
+    }
+
+    fn RAND(&mut self, register: Target_Register, value: u8) {
+       // Generate random number then call SET() 
+       let mut number: u8 = self.rng.gen();
+       number &= value;
+
+       self.SET(register, number);
+    }
+
+    fn DRAW(&mut self, register1: Target_Register, register2: Target_Register, height: u8) -> Result<(), ExecError> {
+        // Pull value from register1 and register2 to use as X and Y coords, then XOR
+        // height rows of 8-bit sprite data starting at memory address I onto the display.
+        // In high resolution a height of 0 draws a 16x16 sprite instead, two bytes per row.
+        // VF is set to 1 if any lit pixel gets turned off (collision), otherwise 0.
+        // Sprite bits landing on dark pixels don't count, only pixels xor_pixel actually turns off
+
+        let x = self.read_register(&register1);
+        let y = self.read_register(&register2);
+
+        // Starting position wraps around the screen
+        let (width, screen_height) = self.resolution();
+        let x = x as usize % width;
+        let y = y as usize % screen_height;
+        let (rows, columns) = if height == 0 && self.display.hires { (16, 16) } else { (height as usize, 8) };
+        let mut collision = false;
+
+        // Check the last sprite byte first so a bad I fails before anything is drawn
+        let bytes = rows * columns / 8;
+        if bytes > 0 {
+            self.memory_index(bytes - 1)?;
+        };
+        self.registers.V[0xF] = 0;
+
+        for row in 0..rows {
+            // Sprite rows are read into the high bits so 8 and 16 pixel wide sprites share one loop
+            let offset = row * columns / 8;
+            let mut sprite = (self.memory[self.memory_index(offset)?] as u16) << 8;
+            if columns == 16 {
+                sprite |= self.memory[self.memory_index(offset + 1)?] as u16;
+            };
+            for bit in 0..columns {
+                if sprite & (0x8000 >> bit) != 0 {
+                    // Only the starting position always wraps, the rest of the sprite wraps or clips by quirk
+                    let (px, py) = (x + bit, y + row);
+                    if self.quirks.clip_sprites && (px >= width || py >= screen_height) {
+                        continue;
+                    };
+                    let px = px % width;
+                    let py = py % screen_height;
+                    if self.display.xor_pixel(px, py) {
+                        collision = true;
+                        self.registers.V[0xF] = 1;
+                    } else {
+                        // Pixels lit during a frame show at full intensity even if they're erased again before it ends
+                        if let Some(level) = self.intensity.get_mut(py * DISPLAY_WIDTH + px) {
+                            *level = 255;
+                        };
+                    };
+                };
+            };
+        };
+
+        self.display_dirty.set(true);
+        self.draw_collided = collision;
+        Ok(())
+    }
+
+    fn SKKEQ(&mut self, register: Target_Register) -> Result<(), ExecError> {
+        // Skip next instruction if key stored in register is pressed
+        // Only the low nibble of the register picks the key
+
+        let key = self.read_register(&register) & 0x0F;
+        if self.keys[key as usize] {
+            self.skip_next()?;
+        };
+        Ok(())
+    }
+
+    fn SKKNEQ(&mut self, register: Target_Register) -> Result<(), ExecError> {
+        // Skip next instruction if key stored in register is not pressed
+
+        let key = self.read_register(&register) & 0x0F;
+        if !self.keys[key as usize] {
+            self.skip_next()?;
+        };
+        Ok(())
+    }
+
+    fn SETXD(&mut self, register: Target_Register) {
+        // register = delay timer
+
+        self.write_register(&register, self.timers.delay);
+    }
+
+    fn STORE(&mut self, register: Target_Register) {
+        // Store key press in register, blocks until key press
+        // Rather than blocking the thread PC is rewound so this instruction runs again next cycle
+        // With key_release_on_fx0a the press is only latched, the register gets written once that key is released
+
+        if !self.quirks.key_release_on_fx0a {
+            match self.keys.iter().position(|&pressed| pressed) {
+                Some(key) => self.write_register(&register, key as u8),
+                None => self.rewind(),
+            };
+            return;
+        };
+
+        match self.pending_key {
+            Some(key) if !self.keys[key as usize] => {
+                self.write_register(&register, key);
+                self.pending_key = None;
+            },
+            Some(_) => self.rewind(),
+            None => {
+                self.pending_key = self.keys.iter().position(|&pressed| pressed).map(|key| key as u8);
+                self.rewind();
+            },
+        };
+    }
+
+    fn SETD(&mut self, register: Target_Register) {
+        // Set delay time to register
+
+        self.timers.delay = self.read_register(&register);
+    }
+
+    fn SETS(&mut self, register: Target_Register) {
+        // Set sound timer to register
+
+        let was_beeping = self.is_beeping();
+        self.timers.sound = self.read_register(&register);
+        self.notify_sound(was_beeping);
+    }
+
+    fn ADDI(&mut self, register: Target_Register) -> Result<(), ExecError> {
+        // Add value in register X to register I
+        // With the addi_overflow quirk enabled VF is set to 1 when I goes past the end of addressable memory (0x1000)
+        let value = match register {
+            Target_Register::I => self.registers.I,
+            Target_Register::PC => self.registers.PC,
+            _ => self.read_register(&register) as u16,
+        };
+
+        let result = self.i_plus(value)?;
+
+        if self.quirks.addi_overflow {
+            self.registers.V[0xF] = if result >= 0x1000 { 1 } else { 0 };
+        };
+        self.registers.I = result;
+        Ok(())
+    }
+
+    fn SPRITE(&mut self, register: Target_Register) {
+        // Set register I to address of register (Chars 0-F in hex represented by 4x5 font)
+        // Only the low nibble of the register picks the character
+
+        let character = self.read_register(&register);
+        self.registers.I = self.font_base.wrapping_add((character & 0x0F) as u16 * 5);
+    }
+
+    fn BCD(&mut self, register: Target_Register) -> Result<(), ExecError> {
+        // Store the hundreds, tens and ones digits of the register at I, I + 1 and I + 2. I is left alone
+        self.memory_index(2)?;
+
+        let value = self.read_register(&register);
+        for (offset, digit) in [value / 100, value / 10 % 10, value % 10].iter().enumerate() {
+            let address = self.memory_index(offset)?;
+            self.store_byte(address, *digit);
+        };
+        Ok(())
+    }
+
+    fn DUMP(&mut self, register: Target_Register) -> Result<(), ExecError> {
+        // Dump registers from V0 to register specified at mem address in register I
+        // With the memory_increment quirk enabled I is left pointing past the last byte written
+
+        let last = Target_Register::register_to_u8(&register);
+        self.memory_index(last as usize)?;
+
+        for x in 0..=last {
+            let address = self.memory_index(x as usize)?;
+            let value = self.read_register(&Target_Register::u8_to_register(x));
+            self.store_byte(address, value);
+        };
+
+        if self.quirks.memory_increment {
+            self.registers.I = self.i_plus(last as u16 + 1)?;
+        };
+        Ok(())
+    }
+
+    fn LOAD(&mut self, register: Target_Register) -> Result<(), ExecError> {
+        // Load registers from V0 to register specified at mem address in register I
+        // Follows the same memory_increment quirk as DUMP
+
+        let last = Target_Register::register_to_u8(&register);
+        self.memory_index(last as usize)?;
+
+        for x in 0..=last {
+            let address = self.memory_index(x as usize)?;
+            self.write_register(&Target_Register::u8_to_register(x), self.memory[address]);
+        };
+
+        if self.quirks.memory_increment {
+            self.registers.I = self.i_plus(last as u16 + 1)?;
+        };
+        Ok(())
+    }
+
+    fn DUMPF(&mut self, register: Target_Register) {
+        // Store registers from V0 to register specified in the RPL flags
+        // There are only 8 flags, so anything past V7 is left out
+
+        let last = Target_Register::register_to_u8(&register).min(7) as usize;
+        self.rpl_flags[..=last].copy_from_slice(&self.registers.V[..=last]);
+    }
+
+    fn LOADF(&mut self, register: Target_Register) {
+        // Load registers from V0 to register specified from the RPL flags, capped at V7 like DUMPF
+
+        let last = Target_Register::register_to_u8(&register).min(7) as usize;
+        self.registers.V[..=last].copy_from_slice(&self.rpl_flags[..=last]);
+    }
+
+    fn BigSprite(&mut self, register: Target_Register) {
+        // Set register I to address of register in the big font (Digits 0-9 represented by 8x10 font)
+        // Only the low nibble of the register picks the digit, and SCHIP has no big A-F so those point past the 9
+
+        let character = self.read_register(&register);
+        self.registers.I = BIG_FONT_BASE + (character & 0x0F) as u16 * 10;
+    }
+
+    fn LoadLongI(&mut self, value: u16) -> Result<(), ExecError> {
+        // I = the address decode read from the word after F000, then step PC over that word too
+
+        self.registers.I = value;
+        self.registers.PC = self.pc_target(self.registers.PC as usize + 2)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SPRITE: [u8; 5] = [0xF0, 0x90, 0x90, 0x90, 0xF0]; // A 4x5 zero, 14 lit pixels
+
+    fn cpu_with(quirks: Quirks, rom: &[u8]) -> CPU {
+        // The ROM goes at 0x200 and SPRITE at 0x300 for the DRAW tests
+        let mut cpu = CPU::new(quirks);
+        cpu.load_rom_bytes(rom).unwrap();
+        cpu.memory[0x300..0x305].copy_from_slice(&SPRITE);
+        cpu
+    }
+
+    fn run(cpu: &mut CPU, cycles: usize) {
+        for _ in 0..cycles {
+            cpu.cycle().unwrap();
+        };
+    }
+
+    fn xochip() -> Quirks {
+        Quirks { xochip: true, ..Quirks::default() }
+    }
+
+    fn lit_pixels(cpu: &CPU) -> usize {
+        cpu.display.rows().map(|row| row.iter().filter(|&&lit| lit).count()).sum()
+    }
+
+    #[test]
+    fn draw_start_coordinates_wrap() {
+        // 70,40 is past the 64x32 screen, so the sprite starts at 6,8
+        let mut cpu = cpu_with(Quirks::default(), &[0x60, 0x46, 0x61, 0x28, 0xA3, 0x00, 0xD0, 0x15]);
+        run(&mut cpu, 4);
+        assert!(cpu.display.get(6, 8));
+        assert!(cpu.display.get(9, 8));
+        assert!(!cpu.display.get(10, 8));
+        assert_eq!(lit_pixels(&cpu), 14);
+    }
+
+    #[test]
+    fn draw_sets_vf_when_a_pixel_is_erased() {
+        let mut cpu = cpu_with(Quirks::default(), &[0xA3, 0x00, 0xD0, 0x15, 0xD0, 0x15]);
+        run(&mut cpu, 2);
+        assert_eq!(cpu.registers.V[0xF], 0);
+        run(&mut cpu, 1);
+        assert_eq!(cpu.registers.V[0xF], 1);
+        assert_eq!(lit_pixels(&cpu), 0);
+    }
+
+    #[test]
+    fn draw_clears_vf_left_from_before() {
+        let mut cpu = cpu_with(Quirks::default(), &[0x6F, 0x01, 0xA3, 0x00, 0xD0, 0x15]);
+        run(&mut cpu, 3);
+        assert_eq!(cpu.registers.V[0xF], 0);
+    }
+
+    #[test]
+    fn clear_screen_turns_every_pixel_off() {
+        let mut cpu = cpu_with(Quirks::default(), &[0xA3, 0x00, 0xD0, 0x15, 0x00, 0xE0]);
+        run(&mut cpu, 2);
+        assert!(lit_pixels(&cpu) > 0);
+        run(&mut cpu, 1);
+        assert_eq!(lit_pixels(&cpu), 0);
+    }
+
+    #[test]
+    fn shftr_puts_the_low_bit_in_vf() {
+        for &(value, flag) in [(0x05, 1), (0x04, 0)].iter() {
+            let mut cpu = cpu_with(Quirks::default(), &[0x61, value, 0x62, 0xFF, 0x81, 0x26]);
+            run(&mut cpu, 3);
+            assert_eq!(cpu.registers.V[1], value >> 1);
+            assert_eq!(cpu.registers.V[0xF], flag);
+        };
+    }
+
+    #[test]
+    fn shftr_with_shift_quirk_shifts_vy_into_vx() {
+        let quirks = Quirks { shift: true, ..Quirks::default() };
+        for &(value, flag) in [(0x05, 1), (0x04, 0)].iter() {
+            let mut cpu = cpu_with(quirks, &[0x61, 0xFF, 0x62, value, 0x81, 0x26]);
+            run(&mut cpu, 3);
+            assert_eq!(cpu.registers.V[1], value >> 1);
+            assert_eq!(cpu.registers.V[2], value);
+            assert_eq!(cpu.registers.V[0xF], flag);
+        };
+    }
+
+    #[test]
+    fn shftl_puts_the_high_bit_in_vf() {
+        let mut cpu = cpu_with(Quirks::default(), &[0x61, 0x80, 0x81, 0x1E]);
+        run(&mut cpu, 2);
+        assert_eq!(cpu.registers.V[1], 0);
+        assert_eq!(cpu.registers.V[0xF], 1);
+
+        let mut cpu = cpu_with(Quirks::default(), &[0x61, 0x40, 0x81, 0x1E]);
+        run(&mut cpu, 2);
+        assert_eq!(cpu.registers.V[1], 0x80);
+        assert_eq!(cpu.registers.V[0xF], 0);
+    }
+
+    #[test]
+    fn suby_underflow_wraps_and_clears_vf() {
+        // V1 = V2 - V1 = 2 - 5
+        let mut cpu = cpu_with(Quirks::default(), &[0x61, 0x05, 0x62, 0x02, 0x81, 0x27]);
+        run(&mut cpu, 3);
+        assert_eq!(cpu.registers.V[1], 253);
+        assert_eq!(cpu.registers.V[0xF], 0);
+    }
+
+    #[test]
+    fn suby_without_borrow_sets_vf() {
+        let mut cpu = cpu_with(Quirks::default(), &[0x61, 0x02, 0x62, 0x05, 0x81, 0x27]);
+        run(&mut cpu, 3);
+        assert_eq!(cpu.registers.V[1], 3);
+        assert_eq!(cpu.registers.V[0xF], 1);
+    }
+
+    #[test]
+    fn subx_without_borrow_sets_vf() {
+        let mut cpu = cpu_with(Quirks::default(), &[0x61, 0x05, 0x62, 0x02, 0x81, 0x25]);
+        run(&mut cpu, 3);
+        assert_eq!(cpu.registers.V[1], 3);
+        assert_eq!(cpu.registers.V[0xF], 1);
+    }
+
+    #[test]
+    fn subx_underflow_wraps_and_clears_vf() {
+        let mut cpu = cpu_with(Quirks::default(), &[0x61, 0x02, 0x62, 0x05, 0x81, 0x25]);
+        run(&mut cpu, 3);
+        assert_eq!(cpu.registers.V[1], 253);
+        assert_eq!(cpu.registers.V[0xF], 0);
+    }
+
+    #[test]
+    fn jmp0_adds_v0_to_the_address() {
+        let mut cpu = cpu_with(Quirks::default(), &[0x60, 0x10, 0xB2, 0x00]);
+        run(&mut cpu, 2);
+        assert_eq!(cpu.registers.PC, 0x210);
+    }
+
+    #[test]
+    fn jmp0_with_jump_quirk_adds_vx() {
+        let quirks = Quirks { jump: true, ..Quirks::default() };
+        // BXNN: X is 2, so V2 is added and V0 is ignored
+        let mut cpu = cpu_with(quirks, &[0x60, 0x30, 0x62, 0x10, 0xB2, 0x00]);
+        run(&mut cpu, 3);
+        assert_eq!(cpu.registers.PC, 0x210);
+    }
+
+    #[test]
+    fn dump_writes_registers_to_memory() {
+        let mut cpu = cpu_with(Quirks::default(), &[0x60, 0x01, 0x61, 0x02, 0x62, 0x03, 0x63, 0x04, 0x64, 0x05, 0x65, 0x06,
+            0xA4, 0x00, 0xF5, 0x55]);
+        run(&mut cpu, 8);
+        assert_eq!(&cpu.memory[0x400..0x407], &[1, 2, 3, 4, 5, 6, 0]);
+        assert_eq!(cpu.registers.I, 0x400);
+    }
+
+    #[test]
+    fn bcd_stores_decimal_digits_at_i() {
+        let mut cpu = cpu_with(Quirks::default(), &[0x63, 254, 0xA4, 0x00, 0xF3, 0x33]);
+        run(&mut cpu, 3);
+        assert_eq!(&cpu.memory[0x400..0x403], &[2, 5, 4]);
+        assert_eq!(cpu.registers.I, 0x400);
+    }
+
+    #[test]
+    fn dump_with_memory_increment_moves_i() {
+        let quirks = Quirks { memory_increment: true, ..Quirks::default() };
+        let mut cpu = cpu_with(quirks, &[0x60, 0x01, 0x61, 0x02, 0xA4, 0x00, 0xF1, 0x55]);
+        run(&mut cpu, 4);
+        assert_eq!(&cpu.memory[0x400..0x402], &[1, 2]);
+        assert_eq!(cpu.registers.I, 0x402);
+    }
+
+    #[test]
+    fn load_reads_back_what_dump_wrote() {
+        let mut cpu = cpu_with(Quirks::default(), &[0x60, 0x11, 0x61, 0x22, 0x62, 0x33, 0x63, 0x44, 0xA4, 0x00, 0xF3, 0x55,
+            0x60, 0x00, 0x61, 0x00, 0x62, 0x00, 0x63, 0x00, 0xF3, 0x65]);
+        run(&mut cpu, 10);
+        assert_eq!(&cpu.registers.V[..4], &[0, 0, 0, 0]);
+        run(&mut cpu, 1);
+        assert_eq!(&cpu.registers.V[..4], &[0x11, 0x22, 0x33, 0x44]);
+        assert_eq!(cpu.registers.V[4], 0);
+    }
+
+    #[test]
+    fn sprite_points_at_the_glyph_for_a() {
+        let mut cpu = cpu_with(Quirks::default(), &[0x6A, 0x0A, 0xFA, 0x29]);
+        run(&mut cpu, 2);
+        let i = cpu.registers.I as usize;
+        assert_eq!(i, FONT_BASE as usize + 0xA * 5);
+        assert_eq!(&cpu.memory[i..i + 5], &[0xF0, 0x90, 0xF0, 0x90, 0x90]);
+    }
+
+    #[test]
+    fn load_rom_bytes_copies_to_program_start() {
+        let mut cpu = CPU::new(Quirks::default());
+        cpu.load_rom_bytes(&[0x60, 0x01, 0x61, 0x02]).unwrap();
+        assert_eq!(&cpu.memory[0x200..0x204], &[0x60, 0x01, 0x61, 0x02]);
+        assert_eq!(cpu.registers.PC, 0x200);
+    }
+
+    #[test]
+    fn run_cycles_runs_that_many_instructions() {
+        let mut cpu = cpu_with(Quirks::default(), &[0x70, 0x01, 0x70, 0x01, 0x70, 0x01, 0x70, 0x01]);
+        cpu.run_cycles(3).unwrap();
+        assert_eq!(cpu.registers.V[0], 3);
+        assert_eq!(cpu.registers.PC, 0x206);
+    }
+
+    #[test]
+    fn run_cycles_stops_at_a_bad_opcode() {
+        let mut cpu = cpu_with(Quirks::default(), &[0x60, 0x01, 0xFF, 0xFF, 0x61, 0x01]);
+        assert!(matches!(cpu.run_cycles(10), Err(ExecError::Decode(DecodeError { opcode: 0xFFFF, pc: 0x202, .. }))));
+        assert_eq!(cpu.registers.V[0], 1);
+        assert_eq!(cpu.registers.V[1], 0);
+    }
+
+    #[test]
+    fn skreq_with_nonzero_low_nibble_is_an_error() {
+        let e = CPU::parse_opcode(0x5001).unwrap_err();
+        assert_eq!(e.opcode, 0x5001);
+    }
+
+    #[test]
+    fn skkeq_skips_only_while_the_key_is_down() {
+        let mut cpu = cpu_with(Quirks::default(), &[0x60, 0x05, 0xE0, 0x9E]);
+        run(&mut cpu, 2);
+        assert_eq!(cpu.registers.PC, 0x204);
+
+        let mut cpu = cpu_with(Quirks::default(), &[0x60, 0x05, 0xE0, 0x9E]);
+        cpu.set_key(5, true);
+        run(&mut cpu, 2);
+        assert_eq!(cpu.registers.PC, 0x206);
+    }
+
+    #[test]
+    fn skkneq_skips_only_while_the_key_is_up() {
+        let mut cpu = cpu_with(Quirks::default(), &[0x60, 0x05, 0xE0, 0xA1]);
+        run(&mut cpu, 2);
+        assert_eq!(cpu.registers.PC, 0x206);
+
+        let mut cpu = cpu_with(Quirks::default(), &[0x60, 0x05, 0xE0, 0xA1]);
+        cpu.set_key(5, true);
+        run(&mut cpu, 2);
+        assert_eq!(cpu.registers.PC, 0x204);
+    }
+
+    #[test]
+    fn store_waits_until_a_key_is_pressed() {
+        let mut cpu = cpu_with(Quirks::default(), &[0xF0, 0x0A]);
+        run(&mut cpu, 3);
+        assert_eq!(cpu.registers.PC, 0x200);
+        cpu.set_key(7, true);
+        run(&mut cpu, 1);
+        assert_eq!(cpu.registers.V[0], 7);
+        assert_eq!(cpu.registers.PC, 0x202);
+    }
+
+    #[test]
+    fn machine_code_call_is_unsupported() {
+        let e = CPU::parse_opcode(0x0123).unwrap_err();
+        assert_eq!(e.kind, DecodeErrorKind::UnsupportedInstruction);
+        assert_eq!(e.opcode, 0x0123);
+    }
+
+    #[test]
+    fn fetch_at_4095_is_an_error_instead_of_panicking() {
+        let mut cpu = CPU::new(Quirks::default());
+        cpu.memory[4095] = 0x60;
+        cpu.registers.PC = 4095;
+        assert!(matches!(cpu.cycle(), Err(ExecError::AddressOverflow { pc: 4095, address: 4097 })));
+        assert_eq!(cpu.registers.PC, 4095);
+    }
+
+    #[test]
+    fn return_on_a_fresh_cpu_underflows() {
+        let mut cpu = cpu_with(Quirks::default(), &[0x00, 0xEE]);
+        assert!(matches!(cpu.cycle(), Err(ExecError::StackUnderflow { pc: 0x200 })));
+    }
+
+    #[test]
+    fn call_then_return_comes_back_after_the_call() {
+        let mut cpu = cpu_with(Quirks::default(), &[0x22, 0x06, 0x60, 0x01, 0x00, 0x00, 0x00, 0xEE]);
+        run(&mut cpu, 1);
+        assert_eq!(cpu.registers.PC, 0x206);
+        assert_eq!(cpu.stack, vec![0x202]);
+        run(&mut cpu, 2);
+        assert_eq!(cpu.registers.V[0], 1);
+        assert!(cpu.stack.is_empty());
+    }
+
+    #[test]
+    fn seventeenth_nested_call_overflows() {
+        // Calls itself forever, each call pushing another return address
+        let mut cpu = cpu_with(Quirks::default(), &[0x22, 0x00]);
+        run(&mut cpu, STACK_DEPTH);
+        assert_eq!(cpu.stack.len(), STACK_DEPTH);
+        assert!(matches!(cpu.cycle(), Err(ExecError::StackOverflow { pc: 0x200 })));
+    }
+
+    #[test]
+    fn add_to_i_past_16_bits_is_an_error() {
+        let mut cpu = CPU::new(Quirks::default());
+        cpu.registers.I = 0xFFF8;
+        let result = cpu.execute(Instruction::ADD { register: Target_Register::I, value: 0x10 });
+        assert!(matches!(result, Err(ExecError::AddressOverflow { address: 0x10008, .. })));
+        assert_eq!(cpu.registers.I, 0xFFF8);
+    }
+
+    #[test]
+    fn addi_leaves_vf_alone_by_default() {
+        let mut cpu = cpu_with(Quirks::default(), &[0x6F, 0x07, 0x60, 0x20, 0xA1, 0x00, 0xF0, 0x1E]);
+        run(&mut cpu, 4);
+        assert_eq!(cpu.registers.I, 0x120);
+        assert_eq!(cpu.registers.V[0xF], 7);
+    }
+
+    #[test]
+    fn addi_overflow_flags_i_past_0xfff() {
+        let quirks = Quirks { addi_overflow: true, ..Quirks::default() };
+        let mut cpu = cpu_with(quirks, &[0x60, 0x01, 0xAF, 0xFF, 0xF0, 0x1E]);
+        run(&mut cpu, 3);
+        assert_eq!(cpu.registers.I, 0x1000);
+        assert_eq!(cpu.registers.V[0xF], 1);
+
+        let mut cpu = cpu_with(quirks, &[0x6F, 0x07, 0x60, 0x20, 0xA1, 0x00, 0xF0, 0x1E]);
+        run(&mut cpu, 4);
+        assert_eq!(cpu.registers.I, 0x120);
+        assert_eq!(cpu.registers.V[0xF], 0);
+    }
+
+    #[test]
+    fn addr_sets_vf_on_carry() {
+        let mut cpu = cpu_with(Quirks::default(), &[0x60, 200, 0x61, 100, 0x80, 0x14]);
+        run(&mut cpu, 3);
+        assert_eq!(cpu.registers.V[0], 44);
+        assert_eq!(cpu.registers.V[0xF], 1);
+    }
+
+    #[test]
+    fn addr_clears_vf_without_carry() {
+        let mut cpu = cpu_with(Quirks::default(), &[0x6F, 0x01, 0x60, 10, 0x61, 20, 0x80, 0x14]);
+        run(&mut cpu, 4);
+        assert_eq!(cpu.registers.V[0], 30);
+        assert_eq!(cpu.registers.V[0xF], 0);
+    }
+
+    #[test]
+    fn addr_into_vf_keeps_the_flag() {
+        let mut cpu = cpu_with(Quirks::default(), &[0x6F, 200, 0x61, 100, 0x8F, 0x14]);
+        run(&mut cpu, 3);
+        assert_eq!(cpu.registers.V[0xF], 1);
+    }
+
+    #[test]
+    fn logic_ops_leave_vf_alone_by_default() {
+        for &op in [0x11u8, 0x12, 0x13].iter() {
+            let mut cpu = cpu_with(Quirks::default(), &[0x6F, 0x05, 0x60, 0x01, 0x61, 0x02, 0x80, op]);
+            run(&mut cpu, 4);
+            assert_eq!(cpu.registers.V[0xF], 5);
+        };
+    }
+
+    #[test]
+    fn logic_ops_clear_vf_with_vf_reset() {
+        let quirks = Quirks { vf_reset: true, ..Quirks::default() };
+        for &(op, result) in [(0x11u8, 3), (0x12, 0), (0x13, 3)].iter() {
+            let mut cpu = cpu_with(quirks, &[0x6F, 0x05, 0x60, 0x01, 0x61, 0x02, 0x80, op]);
+            run(&mut cpu, 4);
+            assert_eq!(cpu.registers.V[0], result);
+            assert_eq!(cpu.registers.V[0xF], 0);
+        };
+    }
+
+    #[test]
+    fn display_uses_assembly_mnemonics() {
+        assert_eq!(CPU::parse_opcode(0xD125).unwrap().to_string(), "DRW V1, V2, 5");
+        assert_eq!(CPU::parse_opcode(0xA123).unwrap().to_string(), "LD I, 0x123");
+        assert_eq!(CPU::parse_opcode(0x3A12).unwrap().to_string(), "SE VA, 0x12");
+        assert_eq!(CPU::parse_opcode(0x5AB0).unwrap().to_string(), "SE VA, VB");
+    }
+
+    #[cfg(feature = "serialize")]
+    #[test]
+    fn load_state_restores_a_saved_machine() {
+        let mut cpu = cpu_with(Quirks::default(), &[0x60, 0x05, 0xA0, 0x50, 0xD0, 0x15, 0x22, 0x0A, 0x00, 0x00, 0x70, 0x01]);
+        run(&mut cpu, 5);
+        let state = cpu.save_state();
+
+        let mut loaded = CPU::new(Quirks::default());
+        loaded.load_state(&state).unwrap();
+        assert_eq!((loaded.registers.V, loaded.registers.I, loaded.registers.PC), (cpu.registers.V, cpu.registers.I, cpu.registers.PC));
+        assert_eq!(&loaded.memory[..], &cpu.memory[..]);
+        assert_eq!(loaded.stack, cpu.stack);
+        assert_eq!(loaded.display, cpu.display);
+
+        run(&mut cpu, 1);
+        run(&mut loaded, 1);
+        assert_eq!(loaded.registers.V, cpu.registers.V);
+    }
+
+    #[cfg(feature = "serialize")]
+    #[test]
+    fn load_state_rejects_garbage() {
+        let mut cpu = CPU::new(Quirks::default());
+        assert!(cpu.load_state(&[1, 2, 3]).is_err());
+    }
+
+    #[test]
+    fn load_rom_reader_returns_the_byte_count() {
+        let mut cpu = CPU::new(Quirks::default());
+        let n = cpu.load_rom_reader(&mut io::Cursor::new(vec![0x60, 0x01, 0x61, 0x02])).unwrap();
+        assert_eq!(n, 4);
+        assert_eq!(&cpu.memory[0x200..0x204], &[0x60, 0x01, 0x61, 0x02]);
+    }
+
+    #[test]
+    fn load_rom_reader_rejects_an_oversize_rom() {
+        let mut cpu = CPU::new(Quirks::default());
+        let rom = vec![0; 4096 - 0x200 + 1];
+        let e = cpu.load_rom_reader(&mut io::Cursor::new(rom)).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn rom_past_the_end_of_memory_is_invalid_data() {
+        let mut cpu = CPU::new(Quirks::default());
+        let e = cpu.load_rom_bytes(&[0; 4000]).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+        assert!(cpu.memory[0x200..].iter().all(|&b| b == 0));
+
+        let largest = vec![0xAA; 4096 - 0x200];
+        cpu.load_rom_bytes(&largest).unwrap();
+        assert_eq!(cpu.memory[4095], 0xAA);
+    }
+
+    fn schip_glyph_at(x: u8) -> CPU {
+        // The 0 glyph's top row is 4 lit pixels from x
+        let quirks = Quirks { schip: true, ..Quirks::default() };
+        let mut cpu = cpu_with(quirks, &[0x60, x, 0xA0, 0x50, 0xD0, 0x15]);
+        run(&mut cpu, 3);
+        cpu
+    }
+
+    #[test]
+    fn scroll_down_moves_rows_down() {
+        let mut cpu = schip_glyph_at(0);
+        cpu.execute(Instruction::SCRD { lines: 2 }).unwrap();
+        assert!(!cpu.display.get(0, 0));
+        assert!(cpu.display.get(0, 2));
+        assert!(cpu.display.get(3, 2));
+    }
+
+    #[test]
+    fn scroll_right_moves_four_columns() {
+        let mut cpu = schip_glyph_at(0);
+        cpu.execute(Instruction::SCRR).unwrap();
+        assert!(!cpu.display.get(0, 0));
+        assert!(cpu.display.get(4, 0));
+        assert!(cpu.display.get(7, 0));
+        assert!(!cpu.display.get(8, 0));
+    }
+
+    #[test]
+    fn scroll_left_moves_four_columns() {
+        let mut cpu = schip_glyph_at(4);
+        cpu.execute(Instruction::SCRL).unwrap();
+        assert!(cpu.display.get(0, 0));
+        assert!(cpu.display.get(3, 0));
+        assert!(!cpu.display.get(4, 0));
+    }
+
+    #[test]
+    fn scrolls_are_unsupported_without_schip() {
+        let mut cpu = cpu_with(Quirks::default(), &[0x00, 0xFB]);
+        match cpu.cycle() {
+            Err(ExecError::Decode(e)) => assert_eq!(e.kind, DecodeErrorKind::UnsupportedInstruction),
+            _ => panic!("expected 00FB to be unsupported"),
+        };
+    }
+
+    #[test]
+    fn resolution_switches_are_unsupported_without_schip() {
+        for opcode in &[[0x00, 0xFF], [0x00, 0xFE]] {
+            let mut cpu = cpu_with(Quirks::default(), opcode);
+            match cpu.cycle() {
+                Err(ExecError::Decode(e)) => assert_eq!(e.kind, DecodeErrorKind::UnsupportedInstruction),
+                _ => panic!("expected {:02X}{:02X} to be unsupported", opcode[0], opcode[1]),
+            };
+        }
+    }
+
+    #[test]
+    fn restore_undoes_cycles() {
+        let mut cpu = cpu_with(Quirks::default(), &[0x60, 0x05, 0xA3, 0x00, 0x22, 0x08, 0x00, 0x00, 0x70, 0x01]);
+        run(&mut cpu, 1);
+        let snapshot = cpu.snapshot();
+        run(&mut cpu, 3);
+        assert_eq!(cpu.registers.V[0], 6);
+
+        cpu.restore(snapshot).unwrap();
+        assert_eq!(cpu.registers.V[0], 5);
+        assert_eq!(cpu.registers.I, 0);
+        assert_eq!(cpu.registers.PC, 0x202);
+        assert!(cpu.stack.is_empty());
+    }
+
+    #[test]
+    fn restore_rejects_a_snapshot_that_doesnt_fit() {
+        let mut cpu = cpu_with(Quirks::default(), &[0x60, 0x05]);
+        let mut snapshot = cpu.snapshot();
+        snapshot.memory.truncate(0x800);
+        run(&mut cpu, 1);
+        assert!(cpu.restore(snapshot).is_err());
+        assert_eq!(cpu.registers.V[0], 5);
+        assert_eq!(cpu.memory.len(), 4096);
+    }
+
+    #[test]
+    fn draw_at_the_bottom_edge_wraps_by_default() {
+        // Rows 2-4 of the 0 glyph wrap to the top of the screen
+        let mut cpu = cpu_with(Quirks::default(), &[0x61, 30, 0xA0, 0x50, 0xD0, 0x15]);
+        run(&mut cpu, 3);
+        assert!(cpu.display.get(0, 30));
+        assert!(cpu.display.get(0, 0));
+        assert!(cpu.display.get(3, 2));
+        assert_eq!(lit_pixels(&cpu), 14);
+    }
+
+    #[test]
+    fn draw_at_the_bottom_edge_clips_with_clip_sprites() {
+        let quirks = Quirks { clip_sprites: true, ..Quirks::default() };
+        let mut cpu = cpu_with(quirks, &[0x61, 30, 0xA0, 0x50, 0xD0, 0x15]);
+        run(&mut cpu, 3);
+        assert!(cpu.display.get(0, 30));
+        assert!(!cpu.display.get(0, 0));
+        assert_eq!(lit_pixels(&cpu), 6);
+    }
+
+    #[test]
+    fn display_dirty_is_cleared_by_framebuffer() {
+        let mut cpu = cpu_with(Quirks::default(), &[0x60, 0x00, 0xA0, 0x50, 0xD0, 0x15]);
+        assert!(cpu.display_dirty());
+        cpu.framebuffer();
+        assert!(!cpu.display_dirty());
+        run(&mut cpu, 2);
+        assert!(!cpu.display_dirty());
+        run(&mut cpu, 1);
+        assert!(cpu.display_dirty());
+        cpu.framebuffer();
+        assert!(!cpu.display_dirty());
+    }
+
+    #[test]
+    fn reset_restarts_the_loaded_rom() {
+        let rom = [0x60, 0x05, 0x70, 0x01, 0xA3, 0x00, 0xF0, 0x55];
+        let mut cpu = cpu_with(Quirks::default(), &rom);
+        run(&mut cpu, 4);
+        cpu.reset();
+        assert_eq!(cpu.registers.V[0], 0);
+        assert_eq!(cpu.registers.PC, 0x200);
+        assert_eq!(&cpu.memory[0x200..0x208], &rom);
+        run(&mut cpu, 4);
+        assert_eq!(cpu.registers.V[0], 6);
+    }
+
+    #[test]
+    fn bad_opcode_is_a_decode_error() {
+        let mut cpu = cpu_with(Quirks::default(), &[0xFF, 0xFF]);
+        match cpu.cycle() {
+            Err(ExecError::Decode(e)) => {
+                assert_eq!(e.opcode, 0xFFFF);
+                assert_eq!(e.pc, 0x200);
+            },
+            _ => panic!("expected a decode error"),
+        };
+    }
+
+    #[test]
+    fn every_decoded_instruction_executes_without_panicking() {
+        // Anything execute can't handle has to come back as an ExecError
+        for opcode in 0..=0xFFFFu16 {
+            if let Ok(instruction) = CPU::parse_opcode(opcode) {
+                let mut cpu = CPU::new(Quirks::default());
+                cpu.registers.PC = 0x202; // As if just fetched from 0x200
+                let _ = cpu.execute(instruction);
+            };
+        };
+    }
+
+    #[test]
+    fn jump_to_odd_address_is_misaligned_with_require_alignment() {
+        let quirks = Quirks { require_alignment: true, ..Quirks::default() };
+        let mut cpu = cpu_with(quirks, &[0x12, 0x05]);
+        run(&mut cpu, 1);
+        match cpu.cycle() {
+            Err(ExecError::Misaligned { pc }) => assert_eq!(pc, 0x205),
+            x => panic!("expected Misaligned, got {:?}", x),
+        };
+    }
+
+    #[test]
+    fn odd_address_runs_without_require_alignment() {
+        // 0x205 holds 60 07, so it's a valid instruction when fetched from the odd address
+        let mut cpu = cpu_with(Quirks::default(), &[0x12, 0x05, 0x00, 0x00, 0x00, 0x60, 0x07]);
+        run(&mut cpu, 2);
+        assert_eq!(cpu.registers.V[0], 7);
+    }
+
+    #[test]
+    fn skeq_skips_when_equal() {
+        let mut cpu = cpu_with(Quirks::default(), &[0x60, 0x05, 0x30, 0x05]);
+        run(&mut cpu, 2);
+        assert_eq!(cpu.registers.PC, 0x206);
+
+        let mut cpu = cpu_with(Quirks::default(), &[0x60, 0x05, 0x30, 0x06]);
+        run(&mut cpu, 2);
+        assert_eq!(cpu.registers.PC, 0x204);
+    }
+
+    #[test]
+    fn skneq_skips_when_not_equal() {
+        let mut cpu = cpu_with(Quirks::default(), &[0x60, 0x05, 0x40, 0x05]);
+        run(&mut cpu, 2);
+        assert_eq!(cpu.registers.PC, 0x204);
+
+        let mut cpu = cpu_with(Quirks::default(), &[0x60, 0x05, 0x40, 0x06]);
+        run(&mut cpu, 2);
+        assert_eq!(cpu.registers.PC, 0x206);
+    }
+
+    #[test]
+    fn loadf_reads_back_what_dumpf_stored() {
+        let mut cpu = cpu_with(Quirks { schip: true, ..Quirks::default() }, &[0x60, 0x01, 0x61, 0x02, 0x62, 0x03, 0x63, 0x04, 0xF3, 0x75,
+            0x60, 0x00, 0x61, 0x00, 0x62, 0x00, 0x63, 0x00, 0xF3, 0x85]);
+        run(&mut cpu, 9);
+        assert_eq!(&cpu.registers.V[..4], &[0, 0, 0, 0]);
+        run(&mut cpu, 1);
+        assert_eq!(&cpu.registers.V[..4], &[1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn dumpf_stops_at_v7() {
+        let mut cpu = CPU::new(Quirks { schip: true, ..Quirks::default() });
+        cpu.registers.V = [0xAA; 16];
+        cpu.execute(Instruction::DUMPF { register: Target_Register::VF }).unwrap();
+        cpu.registers.V = [0; 16];
+        cpu.execute(Instruction::LOADF { register: Target_Register::VF }).unwrap();
+        assert_eq!(&cpu.registers.V[..8], &[0xAA; 8]);
+        assert_eq!(&cpu.registers.V[8..], &[0; 8]);
+    }
+
+    #[test]
+    fn with_layout_runs_from_its_program_start() {
+        let mut cpu = CPU::with_layout(Quirks::default(), MEM_SIZE, 0x600);
+        cpu.load_rom_bytes(&[0x60, 0x07, 0x16, 0x04, 0x00, 0x00]).unwrap();
+        assert_eq!(cpu.registers.PC, 0x600);
+        assert_eq!(cpu.memory[0x600], 0x60);
+        assert_eq!(cpu.memory[0x200], 0);
+        run(&mut cpu, 2);
+        assert_eq!(cpu.registers.V[0], 7);
+        assert_eq!(cpu.registers.PC, 0x604);
+    }
+
+    #[test]
+    fn step_reports_what_it_ran() {
+        let mut cpu = cpu_with(Quirks::default(), &[0x6A, 0x42]);
+        let step = cpu.step().unwrap();
+        assert_eq!(step.pc, 0x200);
+        assert_eq!(step.opcode, 0x6A42);
+        assert!(matches!(step.instruction, Instruction::SET { register: Target_Register::VA, value: 0x42 }));
+        assert!(!step.display_changed);
+        assert_eq!(cpu.registers.V[0xA], 0x42);
+    }
+
+    fn rand_sequence(cpu: &mut CPU) -> Vec<u8> {
+        (0..8).map(|_| {
+            cpu.registers.PC = 0x200;
+            run(cpu, 1);
+            cpu.registers.V[0]
+        }).collect()
+    }
+
+    #[test]
+    fn same_seed_gives_the_same_rand_sequence() {
+        let mut a = CPU::with_seed(42);
+        a.load_rom_bytes(&[0xC0, 0xFF]).unwrap();
+        let mut b = CPU::with_seed(42);
+        b.load_rom_bytes(&[0xC0, 0xFF]).unwrap();
+        let sequence = rand_sequence(&mut a);
+        assert_eq!(sequence, rand_sequence(&mut b));
+
+        a.reseed(42);
+        assert_eq!(sequence, rand_sequence(&mut a));
+    }
+
+    #[test]
+    fn memory_access_inside_and_outside_memory() {
+        let mut cpu = cpu_with(Quirks::default(), &[0x60, 0x01]);
+        assert_eq!(cpu.read_memory(0x200), Some(0x60));
+        assert_eq!(cpu.read_memory(0xFFF), Some(0));
+        assert_eq!(cpu.read_memory(0x1000), None);
+
+        assert_eq!(cpu.write_memory(0x300, 7), Ok(()));
+        assert_eq!(cpu.read_memory(0x300), Some(7));
+        assert_eq!(cpu.write_memory(0x1000, 7), Err(()));
+    }
+
+    #[test]
+    fn run_cycles_stops_when_halted() {
+        let mut cpu = cpu_with(Quirks::default(), &[0x70, 0x01, 0x12, 0x02]);
+        cpu.run_cycles(10).unwrap();
+        assert!(cpu.is_halted());
+        assert_eq!(cpu.registers.V[0], 1);
+    }
+
+    #[test]
+    fn jump_to_itself_halts() {
+        let mut cpu = cpu_with(Quirks::default(), &[0x12, 0x00]);
+        assert!(!cpu.is_halted());
+        run(&mut cpu, 1);
+        assert!(cpu.is_halted());
+        assert_eq!(cpu.registers.PC, 0x200);
+    }
+
+    #[test]
+    fn jump_elsewhere_doesnt_halt() {
+        let mut cpu = cpu_with(Quirks::default(), &[0x12, 0x02, 0x12, 0x00]);
+        run(&mut cpu, 2);
+        assert!(!cpu.is_halted());
+    }
+
+    #[test]
+    fn cloned_registers_dont_follow_the_cpu() {
+        let mut cpu = cpu_with(Quirks::default(), &[0x60, 0x01]);
+        let before = cpu.registers().clone();
+        assert!(before == *cpu.registers());
+        run(&mut cpu, 1);
+        assert!(before != *cpu.registers());
+        assert_eq!(before.V[0], 0);
+        assert_eq!(before.PC, 0x200);
+        assert_eq!(cpu.registers().V[0], 1);
+    }
+
+    #[test]
+    fn display_wait_ends_the_frame_after_draw() {
+        let rom = [0xD0, 0x05, 0x61, 0x01, 0x12, 0x04];
+        let quirks = Quirks { display_wait: true, ..Quirks::default() };
+        let mut cpu = cpu_with(quirks, &rom);
+        cpu.run_frame(600).unwrap();
+        assert_eq!(cpu.registers.PC, 0x202);
+        assert_eq!(cpu.registers.V[1], 0);
+
+        let mut cpu = cpu_with(Quirks::default(), &rom);
+        cpu.run_frame(600).unwrap();
+        assert_eq!(cpu.registers.V[1], 1);
+    }
+
+    #[test]
+    fn last_draw_collided_follows_draws_only() {
+        let mut cpu = cpu_with(Quirks::default(), &[0xA0, 0x50, 0xD0, 0x15, 0xD0, 0x15, 0x6F, 0x00]);
+        run(&mut cpu, 2);
+        assert!(!cpu.last_draw_collided());
+        run(&mut cpu, 1);
+        assert!(cpu.last_draw_collided());
+        // VF is just a register, writing it doesn't change what the last DRAW did
+        run(&mut cpu, 1);
+        assert!(cpu.last_draw_collided());
+    }
+
+    #[test]
+    fn mnemonic_is_the_first_word_of_the_display() {
+        for &opcode in [0x00E0u16, 0x00EE, 0x1234, 0x6A42, 0x8124, 0xA123, 0xD125, 0xE09E, 0xF029, 0xF065].iter() {
+            let instruction = CPU::parse_opcode(opcode).unwrap();
+            let display = instruction.to_string();
+            assert_eq!(display.split(' ').next(), Some(instruction.mnemonic()));
+            assert!(!instruction.description().is_empty());
+        };
+        assert_eq!(Instruction::DRAW { register1: Target_Register::V0, register2: Target_Register::V1, height: 5 }.mnemonic(), "DRW");
+        assert_eq!(Instruction::Display.description(), "Clear the screen");
+    }
+
+    #[test]
+    fn addr_of_a_register_with_itself_doubles_it() {
+        let mut cpu = cpu_with(Quirks::default(), &[0x63, 100, 0x83, 0x34]);
+        run(&mut cpu, 2);
+        assert_eq!(cpu.registers.V[3], 200);
+        assert_eq!(cpu.registers.V[0xF], 0);
+    }
+
+    #[test]
+    fn xor_of_a_register_with_itself_is_zero() {
+        let mut cpu = cpu_with(Quirks::default(), &[0x63, 0x5A, 0x83, 0x33]);
+        run(&mut cpu, 2);
+        assert_eq!(cpu.registers.V[3], 0);
+    }
+
+    #[test]
+    fn subx_of_a_register_from_itself_is_zero_without_borrow() {
+        let mut cpu = cpu_with(Quirks::default(), &[0x63, 0x5A, 0x83, 0x35]);
+        run(&mut cpu, 2);
+        assert_eq!(cpu.registers.V[3], 0);
+        assert_eq!(cpu.registers.V[0xF], 1);
+    }
+
+    #[test]
+    fn key_release_on_fx0a_stores_the_key_once_released() {
+        let quirks = Quirks { key_release_on_fx0a: true, ..Quirks::default() };
+        let mut cpu = cpu_with(quirks, &[0xF0, 0x0A]);
+        cpu.set_key(3, true);
+        run(&mut cpu, 2);
+        assert_eq!(cpu.registers.PC, 0x200);
+        assert_eq!(cpu.registers.V[0], 0);
+        cpu.set_key(3, false);
+        run(&mut cpu, 1);
+        assert_eq!(cpu.registers.V[0], 3);
+        assert_eq!(cpu.registers.PC, 0x202);
+    }
+
+    #[test]
+    fn fx0a_stores_on_press_by_default() {
+        let mut cpu = cpu_with(Quirks::default(), &[0xF0, 0x0A]);
+        cpu.set_key(3, true);
+        run(&mut cpu, 1);
+        assert_eq!(cpu.registers.V[0], 3);
+        assert_eq!(cpu.registers.PC, 0x202);
+    }
+
+    #[test]
+    fn instructions_walks_the_loaded_rom() {
+        let cpu = cpu_with(Quirks::default(), &[0x60, 0x01, 0xA1, 0x23, 0xFF, 0xFF]);
+        let listing: Vec<_> = cpu.instructions().collect();
+        assert_eq!(listing.len(), 3);
+        assert_eq!(listing[0].0, 0x200);
+        assert!(matches!(listing[0].1, Ok(Instruction::SET { register: Target_Register::V0, value: 1 })));
+        assert!(matches!(listing[1].1, Ok(Instruction::SETI { value: 0x123 })));
+        assert_eq!(listing[2].0, 0x204);
+        assert_eq!(listing[2].1.as_ref().unwrap_err().pc, 0x204);
+    }
+
+    #[test]
+    fn erased_pixels_fade_each_tick() {
+        let mut cpu = cpu_with(Quirks::default(), &[0xA0, 0x50, 0xD0, 0x15, 0x00, 0xE0]);
+        cpu.set_ghosting(true);
+        run(&mut cpu, 2);
+        assert_eq!(cpu.pixel_intensity()[0], 255);
+        run(&mut cpu, 1);
+        let mut levels = Vec::new();
+        for _ in 0..5 {
+            cpu.tick_timers();
+            levels.push(cpu.pixel_intensity()[0]);
+        };
+        assert_eq!(levels, vec![191, 127, 63, 0, 0]);
+    }
+
+    #[test]
+    fn lit_pixels_stay_at_full_intensity() {
+        let mut cpu = cpu_with(Quirks::default(), &[0xA0, 0x50, 0xD0, 0x15]);
+        cpu.set_ghosting(true);
+        run(&mut cpu, 2);
+        cpu.tick_timers();
+        cpu.tick_timers();
+        assert_eq!(cpu.pixel_intensity()[0], 255);
+        assert_eq!(cpu.pixel_intensity()[4], 0);
+    }
+
+    #[test]
+    fn every_decodable_opcode_encodes_back_to_itself() {
+        for opcode in 0..=0xFFFF {
+            if let Ok(instruction) = CPU::parse_opcode(opcode) {
+                assert_eq!(instruction.to_opcode(), Some(opcode), "{:04X} decoded as {}", opcode, instruction);
+            };
+        };
+    }
+
+    #[test]
+    fn timers_count_down_and_stop_at_zero() {
+        let mut cpu = CPU::new(Quirks::default());
+        cpu.set_delay_timer(3);
+        cpu.set_sound_timer(2);
+        assert_eq!(cpu.delay_timer(), 3);
+        assert_eq!(cpu.sound_timer(), 2);
+        assert!(cpu.is_beeping());
+        for _ in 0..4 {
+            cpu.decrement_timers();
+        };
+        assert_eq!(cpu.delay_timer(), 0);
+        assert_eq!(cpu.sound_timer(), 0);
+        assert!(!cpu.is_beeping());
+    }
+
+    #[test]
+    fn delay_timer_round_trips_through_registers() {
+        let mut cpu = cpu_with(Quirks::default(), &[0x60, 10, 0xF0, 0x15, 0xF1, 0x07]);
+        run(&mut cpu, 3);
+        assert_eq!(cpu.delay_timer(), 10);
+        assert_eq!(cpu.registers.V[1], 10);
+    }
+
+    // These used to overflow and panic in debug builds, which is what cargo test runs
+    #[test]
+    fn add_to_v_register_wraps() {
+        let mut cpu = cpu_with(Quirks::default(), &[0x60, 0xFF, 0x70, 0x02]);
+        run(&mut cpu, 2);
+        assert_eq!(cpu.registers.V[0], 1);
+    }
+
+    #[test]
+    fn addi_past_16_bits_is_an_error() {
+        let mut cpu = cpu_with(Quirks::default(), &[0x60, 0x10, 0xF0, 0x1E]);
+        cpu.registers.I = 0xFFF8;
+        run(&mut cpu, 1);
+        assert!(matches!(cpu.cycle(), Err(ExecError::AddressOverflow { pc: 0x202, address: 0x10008 })));
+        assert_eq!(cpu.registers.I, 0xFFF8);
+    }
+
+    #[test]
+    fn dump_bumping_i_past_16_bits_is_an_error() {
+        let quirks = Quirks { memory_increment: true, ..Quirks::default() };
+        let mut cpu = cpu_with(quirks, &[0xF1, 0x55]);
+        cpu.registers.I = 0xFFFF;
+        assert!(matches!(cpu.cycle(), Err(ExecError::AddressOverflow { pc: 0x200, address: 0x10001 })));
+    }
+
+    #[test]
+    fn jmp0_past_the_end_of_memory_is_an_error() {
+        // 0xFFF + 0xFF lands past 0x1000
+        let mut cpu = cpu_with(Quirks::default(), &[0x60, 0xFF, 0xBF, 0xFF]);
+        run(&mut cpu, 1);
+        assert!(matches!(cpu.cycle(), Err(ExecError::AddressOverflow { pc: 0x202, address: 0x10FE })));
+    }
+
+    #[test]
+    fn store_in_the_last_word_of_memory_runs_off_the_end() {
+        // Fetching FX0A from the last word would leave PC past the end of memory, so it never gets to wait for a key
+        let mut cpu = CPU::new(Quirks::default());
+        cpu.registers.PC = MEM_SIZE as u16 - 2;
+        cpu.memory[MEM_SIZE - 2] = 0xF0;
+        cpu.memory[MEM_SIZE - 1] = 0x0A;
+        assert!(matches!(cpu.cycle(), Err(ExecError::AddressOverflow { pc: 0xFFE, address: 0x1000 })));
+        assert_eq!(cpu.registers.PC, MEM_SIZE as u16 - 2);
+    }
+
+    #[test]
+    fn skip_past_the_end_of_memory_is_an_error() {
+        let mut cpu = CPU::new(Quirks::default());
+        cpu.memory[MEM_SIZE - 4] = 0x30;
+        cpu.registers.PC = MEM_SIZE as u16 - 4;
+        assert!(matches!(cpu.cycle(), Err(ExecError::AddressOverflow { pc: 0xFFC, address: 0x1000 })));
+    }
+
+    #[cfg(feature = "serialize")]
+    #[test]
+    fn to_json_includes_the_registers() {
+        let cpu = cpu_with(Quirks::default(), &[0x60, 0x01]);
+        let json = cpu.to_json();
+        assert!(json.contains("\"V\""));
+        assert!(json.contains("\"I\""));
+        assert!(json.contains("\"PC\""));
+    }
+
+    #[test]
+    fn xor_pixel_reports_turning_a_pixel_off() {
+        let mut fb = Framebuffer::new();
+        assert!(!fb.xor_pixel(1, 1));
+        assert!(fb.get(1, 1));
+        assert!(fb.xor_pixel(1, 1));
+        assert!(!fb.get(1, 1));
+        assert!(!fb.xor_pixel(1, 1));
+    }
+
+    #[test]
+    fn qwerty_maps_the_left_hand_block() {
+        let map = KeyMap::qwerty();
+        assert_eq!(map.map_key('1'), Some(0x1));
+        assert_eq!(map.map_key('4'), Some(0xC));
+        assert_eq!(map.map_key('q'), Some(0x4));
+        assert_eq!(map.map_key('Q'), Some(0x4));
+        assert_eq!(map.map_key('x'), Some(0x0));
+        assert_eq!(map.map_key('v'), Some(0xF));
+        assert_eq!(map.map_key('p'), None);
+    }
+
+    #[test]
+    fn key_map_set_replaces_the_old_key() {
+        let mut map = KeyMap::qwerty();
+        map.set(0x0, 'm');
+        assert_eq!(map.map_key('m'), Some(0x0));
+        assert_eq!(map.map_key('x'), None);
+    }
+
+    #[test]
+    fn stats_count_by_category() {
+        let mut cpu = cpu_with(Quirks::default(), &[0x60, 0x01, 0x70, 0x01, 0xA0, 0x50, 0xD0, 0x15, 0x12, 0x08]);
+        cpu.run_cycles(10).unwrap();
+        let stats = cpu.stats();
+        assert_eq!(stats.cycles, 5);
+        for &category in ["move", "arithmetic", "memory", "display", "flow"].iter() {
+            assert_eq!(stats.categories.get(category), Some(&1), "{}", category);
+        };
+        cpu.reset_stats();
+        assert_eq!(cpu.stats().cycles, 0);
+        assert!(cpu.stats().categories.is_empty());
+    }
+
+    #[test]
+    fn exit_halts_with_schip() {
+        let mut cpu = cpu_with(Quirks { schip: true, ..Quirks::default() }, &[0x00, 0xFD]);
+        run(&mut cpu, 1);
+        assert!(cpu.is_halted());
+        assert_eq!(cpu.registers.PC, 0x200);
+    }
+
+    #[test]
+    fn exit_is_unsupported_without_schip() {
+        let mut cpu = cpu_with(Quirks::default(), &[0x00, 0xFD]);
+        match cpu.cycle() {
+            Err(ExecError::Decode(e)) => assert_eq!(e.kind, DecodeErrorKind::UnsupportedInstruction),
+            _ => panic!("expected 00FD to be unsupported"),
+        };
+        assert!(!cpu.is_halted());
+    }
+
+    #[test]
+    fn draw_past_the_end_of_memory_wraps() {
+        // Rows 3 and 4 come from addresses 0 and 1
+        let mut cpu = cpu_with(Quirks::default(), &[0xAF, 0xFD, 0xD0, 0x15]);
+        cpu.memory[0] = 0xFF;
+        run(&mut cpu, 2);
+        assert!(cpu.display.get(7, 3));
+    }
+
+    #[test]
+    fn draw_past_the_end_of_memory_is_an_error_with_strict_index() {
+        let quirks = Quirks { strict_index: true, ..Quirks::default() };
+        let mut cpu = cpu_with(quirks, &[0xAF, 0xFD, 0xD0, 0x15]);
+        run(&mut cpu, 1);
+        assert!(matches!(cpu.cycle(), Err(ExecError::IndexOutOfRange { pc: 0x202, .. })));
+        assert_eq!(lit_pixels(&cpu), 0);
+    }
+
+    #[test]
+    fn index_out_of_range_names_the_address() {
+        let quirks = Quirks { strict_index: true, ..Quirks::default() };
+        let mut cpu = cpu_with(quirks, &[0xAF, 0xFF, 0xF1, 0x55]);
+        run(&mut cpu, 1);
+        assert!(matches!(cpu.cycle(), Err(ExecError::IndexOutOfRange { pc: 0x202, address: 0x1000 })));
+    }
+
+    #[test]
+    fn replay_presses_keys_on_the_recorded_cycles() {
+        let rom = [0x70, 0x01, 0x12, 0x00];
+        let mut recorded = cpu_with(Quirks::default(), &rom);
+        recorded.start_recording();
+        let mut seen = Vec::new();
+        for cycle in 0..5 {
+            match cycle {
+                1 => recorded.set_key(5, true),
+                3 => recorded.set_key(5, false),
+                _ => (),
+            };
+            run(&mut recorded, 1);
+            seen.push(recorded.keys[5]);
+        };
+        let log = recorded.stop_recording();
+        assert_eq!(log.events(), &[(1, 5, true), (3, 5, false)]);
+
+        let mut replayed = cpu_with(Quirks::default(), &rom);
+        replayed.play_recording(&log);
+        let mut replay = Vec::new();
+        for _ in 0..5 {
+            run(&mut replayed, 1);
+            replay.push(replayed.keys[5]);
+        };
+        assert_eq!(replay, seen);
+    }
+
+    #[test]
+    fn plain_rom_is_detected_as_chip8() {
+        let variant = detect_variant(&[0x60, 0x01, 0xA0, 0x50, 0xD0, 0x15]);
+        assert_eq!(variant.platform, Platform::Chip8);
+        assert_eq!(variant.quirks, Quirks::cosmac_vip());
+        assert_eq!(variant.confidence, 0.5);
+    }
+
+    #[test]
+    fn each_schip_instruction_kind_adds_confidence() {
+        for &rom in [[0x00, 0xC1], [0x00, 0xFF], [0xF0, 0x75], [0x00, 0xFD]].iter() {
+            let variant = detect_variant(&rom);
+            assert_eq!(variant.platform, Platform::Schip);
+            assert_eq!(variant.quirks, Quirks::schip());
+            assert!((variant.confidence - 0.6).abs() < 1e-6);
+        };
+    }
+
+    #[test]
+    fn detect_variant_confidence_is_capped() {
+        let variant = detect_variant(&[0x00, 0xC1, 0x00, 0xFF, 0xF0, 0x75, 0x00, 0xFD]);
+        assert_eq!(variant.platform, Platform::Schip);
+        assert_eq!(variant.confidence, 0.95);
+    }
+
+    #[test]
+    fn pre_exec_hook_sees_every_draw() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Arc;
+
+        let draws = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&draws);
+        let mut cpu = cpu_with(Quirks::default(), &[0xA0, 0x50, 0xD0, 0x15, 0xD0, 0x15, 0x12, 0x06]);
+        cpu.set_pre_exec(move |instruction, _| {
+            if let Instruction::DRAW { .. } = instruction {
+                counter.fetch_add(1, Ordering::SeqCst);
+            };
+            true
+        });
+        cpu.run_cycles(10).unwrap();
+        assert_eq!(draws.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn pre_exec_hook_can_skip_draws() {
+        let mut cpu = cpu_with(Quirks::default(), &[0xA0, 0x50, 0xD0, 0x15, 0x60, 0x01]);
+        cpu.set_pre_exec(|instruction, _| !matches!(instruction, Instruction::DRAW { .. }));
+        run(&mut cpu, 3);
+        assert_eq!(lit_pixels(&cpu), 0);
+        assert_eq!(cpu.registers.V[0], 1);
+    }
+
+    #[test]
+    fn draw_without_overlap_doesnt_collide() {
+        // The 0 glyph at 0,0 then again at 8,0
+        let mut cpu = cpu_with(Quirks::default(), &[0xA0, 0x50, 0xD0, 0x15, 0x60, 0x08, 0xD0, 0x15]);
+        run(&mut cpu, 4);
+        assert_eq!(cpu.registers.V[0xF], 0);
+    }
+
+    #[test]
+    fn draw_collides_on_pixels_wrapped_past_the_right_edge() {
+        // The 0 glyph at 0,0 then at 62,0, where only the two columns wrapped round to x 0 and 1 overlap
+        let rom = [0xA0, 0x50, 0xD0, 0x15, 0x60, 0x3E, 0xD0, 0x15];
+        let mut cpu = cpu_with(Quirks::default(), &rom);
+        run(&mut cpu, 4);
+        assert_eq!(cpu.registers.V[0xF], 1);
+
+        // Clipped, the same columns fall off the edge instead and nothing collides
+        let mut cpu = cpu_with(Quirks { clip_sprites: true, ..Quirks::default() }, &rom);
+        run(&mut cpu, 4);
+        assert_eq!(cpu.registers.V[0xF], 0);
+    }
+
+    #[test]
+    fn draw_over_pixels_already_erased_doesnt_collide() {
+        // The third DRAW overlaps the first two, but they left those pixels off
+        let mut cpu = cpu_with(Quirks::default(), &[0xA0, 0x50, 0xD0, 0x15, 0xD0, 0x15, 0xD0, 0x15]);
+        run(&mut cpu, 4);
+        assert_eq!(cpu.registers.V[0xF], 0);
+        assert_eq!(lit_pixels(&cpu), 14);
+    }
+
+    #[test]
+    fn ppm_has_a_header_and_a_triple_per_pixel() {
+        let cpu = cpu_with(Quirks::default(), &[]);
+        let header = b"P6\n128 64\n255\n";
+        let ppm = cpu.screenshot_ppm(2);
+        assert_eq!(&ppm[..header.len()], header);
+        assert_eq!(ppm.len(), header.len() + 128 * 64 * 3);
+    }
+
+    #[test]
+    fn ppm_shows_lit_pixels() {
+        let mut cpu = cpu_with(Quirks::default(), &[0xA0, 0x50, 0xD0, 0x15]);
+        run(&mut cpu, 2);
+        let header = b"P6\n64 32\n255\n".len();
+        let ppm = cpu.screenshot_ppm(1);
+        assert_eq!(&ppm[header..header + 3], &[255, 255, 255]);
+        assert_eq!(&ppm[header + 4 * 3..header + 5 * 3], &[0, 0, 0]);
+    }
+
+    #[test]
+    fn fuzz_rom_survives_random_bytes() {
+        let mut rng = StdRng::seed_from_u64(87);
+        for _ in 0..500 {
+            let len = rng.gen_range(0, 64);
+            let rom: Vec<u8> = (0..len).map(|_| rng.gen()).collect();
+            fuzz_rom(&rom, 1000);
+        };
+    }
+
+    #[test]
+    fn fuzz_rom_reports_how_the_run_ended() {
+        assert!(matches!(fuzz_rom(&[0x12, 0x00], 10), FuzzResult::Halted { cycles: 1 }));
+        assert!(matches!(fuzz_rom(&[0x60, 0x01, 0xFF, 0xFF], 10), FuzzResult::Error { cycles: 1, .. }));
+        assert!(matches!(fuzz_rom(&[0x70, 0x01, 0x12, 0x00], 10), FuzzResult::CycleLimit));
+        assert!(matches!(fuzz_rom(&[0; 4000], 10), FuzzResult::TooLarge));
+    }
+
+    #[test]
+    fn long_i_load_steps_over_its_operand() {
+        let mut cpu = cpu_with(xochip(), &[0xF0, 0x00, 0x0A, 0xBC]);
+        run(&mut cpu, 1);
+        assert_eq!(cpu.registers.I, 0x0ABC);
+        assert_eq!(cpu.registers.PC, 0x204);
+    }
+
+    #[test]
+    fn skip_over_long_i_load_moves_four_bytes() {
+        // V0 is 0, so 3000 skips the whole F000 NNNN
+        let mut cpu = cpu_with(xochip(), &[0x30, 0x00, 0xF0, 0x00, 0x0A, 0xBC, 0x60, 0x01]);
+        run(&mut cpu, 2);
+        assert_eq!(cpu.registers.I, 0);
+        assert_eq!(cpu.registers.V[0], 1);
+        assert_eq!(cpu.registers.PC, 0x208);
+    }
+
+    #[test]
+    fn skip_over_plain_instruction_moves_two_bytes_with_xochip() {
+        let mut cpu = cpu_with(xochip(), &[0x30, 0x00, 0x60, 0x05, 0x61, 0x01]);
+        run(&mut cpu, 2);
+        assert_eq!(cpu.registers.V[0], 0);
+        assert_eq!(cpu.registers.V[1], 1);
+        assert_eq!(cpu.registers.PC, 0x206);
+    }
+
+    #[test]
+    fn hook_skipping_long_i_load_steps_over_its_operand() {
+        let mut cpu = cpu_with(xochip(), &[0xF0, 0x00, 0x0A, 0xBC, 0x60, 0x01]);
+        cpu.set_pre_exec(|instruction, _| !matches!(instruction, Instruction::LoadLongI { .. }));
+        let step = cpu.step().unwrap();
+        assert!(!step.executed);
+        assert_eq!(cpu.registers.PC, 0x204);
+        run(&mut cpu, 1);
+        assert_eq!(cpu.registers.I, 0);
+        assert_eq!(cpu.registers.V[0], 1);
+    }
+
+    #[test]
+    fn instructions_steps_over_long_i_load_operands() {
+        let cpu = cpu_with(xochip(), &[0xF0, 0x00, 0x0A, 0xBC, 0x60, 0x01]);
+        let addresses: Vec<u16> = cpu.instructions().map(|(address, _)| address).collect();
+        assert_eq!(addresses, vec![0x200, 0x204]);
+    }
+
+    #[test]
+    fn unknown_sub_opcodes_name_their_low_bits() {
+        for &(opcode, sub_opcode) in [(0x5001u16, 0x1u8), (0x800F, 0xF), (0x9001, 0x1), (0xE000, 0x00), (0xF0FF, 0xFF)].iter() {
+            let e = CPU::parse_opcode(opcode).unwrap_err();
+            assert_eq!(e.kind, DecodeErrorKind::UnknownSubOpcode { sub_opcode }, "{:04X}", opcode);
+            assert_eq!(e.opcode, opcode);
+        };
+    }
+
+    #[test]
+    fn load_font_moves_the_font_fx29_points_at() {
+        let font: Vec<u8> = (0..80).collect();
+        let mut cpu = cpu_with(Quirks::default(), &[0x60, 0x03, 0xF0, 0x29]);
+        cpu.load_font(&font, 0x120).unwrap();
+        run(&mut cpu, 2);
+        assert_eq!(cpu.font_base(), 0x120);
+        assert_eq!(cpu.registers.I, 0x120 + 15);
+        assert_eq!(cpu.memory[0x12F], 15);
+    }
+
+    #[test]
+    fn load_font_rejects_a_base_with_no_room_for_16_glyphs() {
+        let mut cpu = CPU::with_layout(Quirks::default(), 0x10000, 0xFFF0);
+        assert!(cpu.load_font(&[0xAA; 5], 0xFFFF).is_err());
+        assert!(cpu.load_font(&[0xAA; 5], 0xFFA0).is_ok());
+        assert_eq!(cpu.font_base(), 0xFFA0);
+    }
+
+    #[test]
+    fn load_font_rejects_running_into_the_program() {
+        let mut cpu = cpu_with(Quirks::default(), &[0x12, 0x00]);
+        assert!(cpu.load_font(&[0xAA; 80], PROGRAM_START).is_err());
+        assert!(cpu.load_font(&[0xAA; 5], PROGRAM_START - 5).is_err());
+        assert_eq!(&cpu.memory[0x200..0x202], &[0x12, 0x00]);
+        assert_eq!(cpu.font_base(), FONT_BASE);
+        assert!(cpu.load_font(&[0xAA; 5], PROGRAM_START - 80).is_ok());
+    }
+
+    #[test]
+    fn big_sprite_points_at_the_big_glyph() {
+        let mut cpu = cpu_with(Quirks { schip: true, ..Quirks::default() }, &[0x67, 0x07, 0xF7, 0x30]);
+        run(&mut cpu, 2);
+        assert_eq!(cpu.registers.I, BIG_FONT_BASE + 7 * 10);
+        let i = cpu.registers.I as usize;
+        assert_eq!(&cpu.memory[i..i + 10], &BIG_FONT_SET[70..80]);
+    }
+
+    #[test]
+    fn load_font_rejects_overlapping_the_big_font() {
+        let mut cpu = CPU::new(Quirks::default());
+        assert!(cpu.load_font(&[0xAA; 80], 0x80).is_err());
+        // 5 bytes stop short of the big font, but FX29 can still reach 80 bytes in
+        assert!(cpu.load_font(&[0xAA; 5], 0x9B).is_err());
+        assert_eq!(cpu.memory[BIG_FONT_BASE as usize], BIG_FONT_SET[0]);
+        assert_eq!(cpu.font_base(), FONT_BASE);
+        assert!(cpu.load_font(&[0xAA; 80], BIG_FONT_BASE + 100).is_ok());
+    }
+
+    #[test]
+    fn builder_applies_every_setting() {
+        let font: Vec<u8> = (0..80).collect();
+        let mut cpu = CPU::builder()
+            .quirks(Quirks::schip())
+            .seed(7)
+            .memory_size(8192)
+            .program_start(0x600)
+            .font(&font, 0x120)
+            .build()
+            .unwrap();
+        assert!(cpu.quirks == Quirks::schip());
+        assert_eq!(cpu.mem_size(), 8192);
+        assert_eq!(cpu.program_start(), 0x600);
+        assert_eq!(cpu.font_base(), 0x120);
+        assert_eq!(&cpu.memory[0x120..0x170], &font[..]);
+
+        cpu.load_rom_bytes(&[0xC0, 0xFF]).unwrap();
+        run(&mut cpu, 1);
+        let mut seeded = CPU::with_seed(7);
+        seeded.load_rom_bytes(&[0xC0, 0xFF]).unwrap();
+        run(&mut seeded, 1);
+        assert_eq!(cpu.registers.V[0], seeded.registers.V[0]);
+    }
+
+    #[test]
+    fn builder_rejects_a_bad_layout() {
+        assert!(CPU::builder().memory_size(0x10001).build().is_err());
+        assert!(CPU::builder().program_start(0x50).build().is_err());
+        assert!(CPU::builder().memory_size(0x400).program_start(0x400).build().is_err());
+        assert!(CPU::builder().memory_size(0x400).program_start(0x300).build().is_ok());
+    }
+
+    #[test]
+    fn skip_at_the_top_of_a_larger_memory_is_an_error() {
+        let mut cpu = CPU::with_layout(Quirks::default(), 8192, PROGRAM_START);
+        cpu.memory[0x1FFC] = 0x30;
+        cpu.memory[0x1FFD] = 0x00;
+        cpu.registers.PC = 0x1FFC;
+        assert!(matches!(cpu.cycle(), Err(ExecError::AddressOverflow { pc: 0x1FFC, address: 0x2000 })));
+    }
+
+    #[test]
+    fn dump_over_run_code_counts_as_a_code_write() {
+        // The DUMP overwrites the SET it ran a moment ago
+        let quirks = Quirks { watch_code_writes: true, ..Quirks::default() };
+        let mut cpu = cpu_with(quirks, &[0x60, 0x12, 0xA2, 0x00, 0xF0, 0x55]);
+        run(&mut cpu, 3);
+        assert_eq!(cpu.stats().code_writes, 1);
+        assert_eq!(cpu.memory[0x200], 0x12);
+    }
+
+    #[test]
+    fn dump_past_the_program_isnt_a_code_write() {
+        let quirks = Quirks { watch_code_writes: true, ..Quirks::default() };
+        let mut cpu = cpu_with(quirks, &[0x60, 0x12, 0xA3, 0x00, 0xF0, 0x55]);
+        run(&mut cpu, 3);
+        assert_eq!(cpu.stats().code_writes, 0);
+    }
+
+    #[test]
+    fn code_writes_arent_counted_without_the_quirk() {
+        let mut cpu = cpu_with(Quirks::default(), &[0x60, 0x12, 0xA2, 0x00, 0xF0, 0x55]);
+        run(&mut cpu, 3);
+        assert_eq!(cpu.stats().code_writes, 0);
+    }
+
+    #[test]
+    fn initialize_stops_the_timers_and_any_replay() {
+        use std::sync::{Arc, Mutex};
+        let mut cpu = cpu_with(Quirks::default(), &[0x12, 0x00]);
+        let beeps = Arc::new(Mutex::new(Vec::new()));
+        let log = Arc::clone(&beeps);
+        cpu.set_sound_callback(move |on| log.lock().unwrap().push(on));
+        cpu.set_sound_timer(200);
+        cpu.set_delay_timer(100);
+        cpu.start_recording();
+        cpu.set_key(3, true);
+        let recording = cpu.stop_recording();
+        cpu.play_recording(&recording);
+
+        cpu.initialize();
+        assert_eq!(cpu.sound_timer(), 0);
+        assert_eq!(cpu.delay_timer(), 0);
+        assert!(!cpu.is_beeping());
+        assert_eq!(*beeps.lock().unwrap(), vec![true, false]);
+        assert!(cpu.playback.is_empty());
+    }
+
+    #[test]
+    fn skreq_skips_when_the_registers_match() {
+        let mut cpu = cpu_with(Quirks::default(), &[0x60, 0x05, 0x61, 0x05, 0x50, 0x10]);
+        run(&mut cpu, 3);
+        assert_eq!(cpu.registers.PC, 0x208);
+
+        let mut cpu = cpu_with(Quirks::default(), &[0x60, 0x05, 0x61, 0x06, 0x50, 0x10]);
+        run(&mut cpu, 3);
+        assert_eq!(cpu.registers.PC, 0x206);
+    }
+
+    #[test]
+    fn skrneq_skips_when_the_registers_differ() {
+        let mut cpu = cpu_with(Quirks::default(), &[0x60, 0x05, 0x61, 0x06, 0x90, 0x10]);
+        run(&mut cpu, 3);
+        assert_eq!(cpu.registers.PC, 0x208);
+
+        let mut cpu = cpu_with(Quirks::default(), &[0x60, 0x05, 0x61, 0x05, 0x90, 0x10]);
+        run(&mut cpu, 3);
+        assert_eq!(cpu.registers.PC, 0x206);
+    }
+
+    #[test]
+    fn register_compared_with_itself_is_equal() {
+        let mut cpu = cpu_with(Quirks::default(), &[0x61, 0x05, 0x51, 0x10]);
+        run(&mut cpu, 2);
+        assert_eq!(cpu.registers.PC, 0x206);
+
+        let mut cpu = cpu_with(Quirks::default(), &[0x61, 0x05, 0x91, 0x10]);
+        run(&mut cpu, 2);
+        assert_eq!(cpu.registers.PC, 0x204);
+    }
+
+    #[test]
+    fn run_stops_when_max_cycles_runs_out() {
+        // Two instruction loop, so it never looks like a jump to itself
+        let mut cpu = cpu_with(Quirks::default(), &[0x70, 0x01, 0x12, 0x00]);
+        cpu.set_max_cycles(Some(100));
+        assert_eq!(cpu.run(1_000_000).unwrap(), RunStatus::BudgetExhausted);
+        assert_eq!(cpu.stats().cycles, 100);
+        assert_eq!(cpu.registers.V[0], 50);
+    }
+
+    #[test]
+    fn run_reports_a_halt_inside_max_cycles() {
+        let mut cpu = cpu_with(Quirks::default(), &[0x12, 0x00]);
+        cpu.set_max_cycles(Some(100));
+        assert_eq!(cpu.run(1_000_000).unwrap(), RunStatus::Halted);
+        assert_eq!(cpu.stats().cycles, 1);
+    }
+
+    #[test]
+    fn shftr_into_vf_keeps_the_flag() {
+        let mut cpu = cpu_with(Quirks::default(), &[0x6F, 0x05, 0x8F, 0xF6]);
+        run(&mut cpu, 2);
+        assert_eq!(cpu.registers.V[0xF], 1);
+    }
+
+    #[test]
+    fn shftl_into_vf_keeps_the_flag() {
+        let mut cpu = cpu_with(Quirks::default(), &[0x6F, 0x40, 0x8F, 0xFE]);
+        run(&mut cpu, 2);
+        assert_eq!(cpu.registers.V[0xF], 0);
+
+        let mut cpu = cpu_with(Quirks::default(), &[0x6F, 0x80, 0x8F, 0xFE]);
+        run(&mut cpu, 2);
+        assert_eq!(cpu.registers.V[0xF], 1);
+    }
+
+    #[derive(Default)]
+    struct MockFrontend {
+        polls: usize,
+        presents: usize,
+        beeps: Vec<bool>,
+        first_pixel: bool, // Pixel (0, 0) in the last frame presented
+        quit_after: Option<usize>, // Polls before asking to quit
+    }
+
+    impl Frontend for MockFrontend {
+        fn poll_input(&mut self) -> Vec<(u8, bool)> {
+            self.polls += 1;
+            if self.polls == 1 { vec![(5, true)] } else { Vec::new() }
+        }
+
+        fn present(&mut self, fb: &Framebuffer) {
+            self.presents += 1;
+            self.first_pixel = fb.get(0, 0);
+        }
+
+        fn beep(&mut self, on: bool) {
+            self.beeps.push(on);
+        }
+
+        fn quit_requested(&self) -> bool {
+            matches!(self.quit_after, Some(x) if self.polls >= x)
+        }
+    }
+
+    // Waits for a key into V0, beeps for 2 ticks, draws the 0 glyph at (0, 0), then loops forever
+    const KEY_BEEP_DRAW: [u8; 16] = [0xF0, 0x0A, 0x61, 0x02, 0xF1, 0x18, 0x62, 0x00, 0xF2, 0x29, 0xD2, 0x25, 0x63, 0x00, 0x12, 0x0C];
+
+    #[test]
+    fn run_with_polls_input_presents_and_beeps() {
+        let mut cpu = cpu_with(Quirks::default(), &KEY_BEEP_DRAW);
+        let mut frontend = MockFrontend { quit_after: Some(10), ..MockFrontend::default() };
+        assert_eq!(run_with(&mut cpu, &mut frontend, 600).unwrap(), RunStatus::Quit);
+        assert_eq!(frontend.polls, 10);
+        assert_eq!(cpu.registers.V[0], 5);
+        assert!(frontend.presents >= 1);
+        assert!(frontend.first_pixel);
+        assert_eq!(frontend.beeps, vec![true, false]);
+    }
+
+    #[test]
+    fn run_with_stops_when_the_program_halts() {
+        let mut cpu = cpu_with(Quirks::default(), &[0x12, 0x00]);
+        let mut frontend = MockFrontend::default();
+        assert_eq!(run_with(&mut cpu, &mut frontend, 600).unwrap(), RunStatus::Halted);
+        assert_eq!(frontend.polls, 1);
+    }
+
+    #[test]
+    fn run_with_keeps_to_max_cycles() {
+        let mut cpu = cpu_with(Quirks::default(), &KEY_BEEP_DRAW);
+        cpu.set_max_cycles(Some(20));
+        let mut frontend = MockFrontend::default();
+        assert_eq!(run_with(&mut cpu, &mut frontend, 600).unwrap(), RunStatus::BudgetExhausted);
+        assert_eq!(cpu.stats().cycles, 20);
+    }
+
+    #[test]
+    fn subx_into_vf_keeps_the_flag() {
+        let mut cpu = cpu_with(Quirks::default(), &[0x6F, 0x05, 0x61, 0x02, 0x8F, 0x15]);
+        run(&mut cpu, 3);
+        assert_eq!(cpu.registers.V[0xF], 1);
+
+        let mut cpu = cpu_with(Quirks::default(), &[0x6F, 0x02, 0x61, 0x05, 0x8F, 0x15]);
+        run(&mut cpu, 3);
+        assert_eq!(cpu.registers.V[0xF], 0);
+    }
+
+    #[test]
+    fn suby_into_vf_keeps_the_flag() {
+        let mut cpu = cpu_with(Quirks::default(), &[0x6F, 0x02, 0x61, 0x05, 0x8F, 0x17]);
+        run(&mut cpu, 3);
+        assert_eq!(cpu.registers.V[0xF], 1);
+    }
+
+    #[test]
+    fn subx_from_vf_reads_it_before_the_flag() {
+        // V1 = V1 - VF = 5 - 2, then VF becomes the no borrow flag
+        let mut cpu = cpu_with(Quirks::default(), &[0x6F, 0x02, 0x61, 0x05, 0x81, 0xF5]);
+        run(&mut cpu, 3);
+        assert_eq!(cpu.registers.V[1], 3);
+        assert_eq!(cpu.registers.V[0xF], 1);
+    }
+
+    #[test]
+    fn display_wait_allows_one_draw_per_frame() {
+        let quirks = Quirks { display_wait: true, ..Quirks::default() };
+        let mut cpu = cpu_with(quirks, &[0xA0, 0x50, 0xD0, 0x15, 0xD0, 0x15, 0x12, 0x02]);
+        for frame in 1..=4 {
+            cpu.run_frame(600).unwrap();
+            assert_eq!(cpu.stats().categories.get("display"), Some(&frame));
+        };
+    }
+
+    #[test]
+    fn disassemble_keeps_words_that_dont_decode() {
+        let listing = disassemble(&[0x60, 0x01, 0xFF, 0xFF, 0x12]);
+        assert_eq!(listing.len(), 3);
+        assert!(matches!(listing[0], (0x200, Ok(Instruction::SET { register: Target_Register::V0, value: 1 }))));
+        match &listing[1] {
+            (0x202, Err(e)) => {
+                assert_eq!(e.opcode, 0xFFFF);
+                assert_eq!(e.pc, 0x202);
+            },
+            x => panic!("expected 0x202 to be a decode error, got {:?}", x),
+        };
+        // An odd byte at the end is padded out with a zero
+        assert!(matches!(listing[2], (0x204, Ok(Instruction::JUMP { address: 0x200 }))));
+    }
+
+    #[test]
+    fn disassemble_wraps_addresses_past_the_16_bit_space() {
+        let rom = vec![0; 0x10000];
+        let listing = disassemble(&rom);
+        assert_eq!(listing.len(), 0x8000);
+        assert_eq!(listing[0x7EFF].0, 0xFFFE);
+        assert_eq!(listing[0x7F00].0, 0x0000);
+        assert_eq!(listing[0x7FFF].0, 0x01FE);
+    }
+}
@@ -0,0 +1,26 @@
+// Uses the crate the way another frontend would, through its public API only
+
+use opcode::{Instruction, Quirks, Target_Register, CPU};
+
+#[test]
+fn runs_a_rom_through_the_public_api() {
+    // Draws the 0 glyph at 0,0 then jumps to itself
+    let mut chip8 = CPU::new(Quirks::default());
+    chip8.load_rom_bytes(&[0x60, 0x00, 0xF0, 0x29, 0xD0, 0x05, 0x12, 0x06]).unwrap();
+    for _ in 0..4 {
+        chip8.cycle().unwrap();
+    };
+
+    assert!(chip8.is_halted());
+    assert_eq!(chip8.registers().PC, 0x206);
+    let framebuffer = chip8.framebuffer();
+    assert!(framebuffer.get(0, 0));
+    assert!(framebuffer.get(3, 4));
+    assert!(!framebuffer.get(1, 1));
+}
+
+#[test]
+fn decodes_through_the_public_api() {
+    assert!(matches!(CPU::parse_opcode(0x6A42), Ok(Instruction::SET { register: Target_Register::VA, value: 0x42 })));
+    assert!(CPU::parse_opcode(0xFFFF).is_err());
+}
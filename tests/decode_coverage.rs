@@ -0,0 +1,35 @@
+// Runs every possible opcode through parse_opcode. Every opcode has to come back as either an
+// Instruction or a DecodeError naming that opcode, anything else (including a panic) fails the test
+
+use std::collections::BTreeMap;
+use opcode::{DecodeErrorKind, CPU};
+
+#[test]
+fn every_opcode_decodes_or_names_itself_in_the_error() {
+    let mut categories: BTreeMap<&'static str, u32> = BTreeMap::new();
+    let mut unknown = 0;
+    let mut unsupported = 0;
+
+    for opcode in 0..=0xFFFFu16 {
+        match CPU::parse_opcode(opcode) {
+            Ok(instruction) => *categories.entry(instruction.category()).or_insert(0) += 1,
+            Err(e) => {
+                assert_eq!(e.opcode, opcode, "{:04X}: error reports opcode {:04X}", opcode, e.opcode);
+                match e.kind {
+                    DecodeErrorKind::UnknownOpcode | DecodeErrorKind::UnknownSubOpcode { .. } => unknown += 1,
+                    DecodeErrorKind::UnsupportedInstruction => unsupported += 1,
+                };
+            },
+        };
+    }
+
+    let decoded: u32 = categories.values().sum();
+    assert_eq!(decoded + unknown + unsupported, 0x10000);
+    // 0NNN machine code calls are reported as unsupported rather than unknown
+    assert!(unsupported > 0);
+    for category in ["flow", "move", "arithmetic", "logic", "random", "display", "input", "timers", "memory"].iter() {
+        assert!(categories.contains_key(category), "nothing decodes to a {} instruction", category);
+    };
+    // Each of the 4096 CXNN opcodes is a RAND
+    assert_eq!(categories["random"], 0x1000);
+}
@@ -0,0 +1,33 @@
+// End to end checks against whole ROMs. Each ROM in tests/roms runs headless until it halts and
+// its screen has to match <name>.txt next to it, 64x32 lines of # (lit) and . (dark).
+// To add one drop <name>.ch8 and <name>.txt into tests/roms and add a rom_test! line for it.
+// examples/rom_check can --bless a reference from a run that's been checked by eye
+
+use opcode::{CPU, Quirks};
+
+const CYCLES: usize = 10_000; // Plenty for a ROM that halts, and a bound on one that doesn't
+
+fn check(name: &str, rom: &[u8], expected: &str) {
+    let mut chip8 = CPU::new(Quirks::default());
+    chip8.load_rom_bytes(rom).unwrap();
+    chip8.run_cycles(CYCLES).unwrap();
+    assert!(chip8.is_halted(), "{} was still running after {} cycles", name, CYCLES);
+
+    let screen = chip8.framebuffer().to_string();
+    assert_eq!(screen.trim_end(), expected.trim_end(), "{} didn't draw its reference screen, got\n{}", name, screen);
+}
+
+macro_rules! rom_test {
+    ($name:ident) => {
+        #[test]
+        fn $name() {
+            check(stringify!($name),
+                include_bytes!(concat!("roms/", stringify!($name), ".ch8")),
+                include_str!(concat!("roms/", stringify!($name), ".txt")));
+        }
+    };
+}
+
+// Adds 5 + 3 and skips a hang on the result, draws an 8 twice so the second draw erases it and
+// sets VF, then draws VF's digit (1) and, behind a 4XNN skip, a 5. A wrong flag or skip changes the digits
+rom_test!(flags_and_skips);